@@ -0,0 +1,129 @@
+// Bloom filters on semijoin keys: a bit array with k hash functions per key. A key that was
+// inserted is always found, a key that was not is found with about the false positive rate the
+// filter was sized for. The semijoin reducer uses one as a cheap pre-pass on large relations:
+// the target rows the filter rejects are dangling for sure, the rest go to the exact semijoin.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use serde::Serialize;
+
+use crate::join::Value;
+
+// by default a semijoin runs the pre-pass with at least this many selected rows in its source:
+// on smaller relations the exact hash set is cheap enough.
+pub const BLOOM_FILTER_ROWS: usize = 100_000;
+
+// the bloom filter pre-pass of the semijoin reducer: the false positive rate (below 0.5) of
+// the filters, and the selected rows a source needs before its semijoins are filtered first.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct BloomFilterPass {
+    pub false_positive_rate: f64,
+    pub min_source_rows: usize,
+}
+
+impl BloomFilterPass {
+    pub fn new(false_positive_rate: f64) -> BloomFilterPass {
+        BloomFilterPass {
+            false_positive_rate,
+            min_source_rows: BLOOM_FILTER_ROWS,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BloomFilter {
+    bits: Vec<u64>,
+    hashes: u32,
+    false_positive_rate: f64,
+}
+
+impl BloomFilter {
+    // a filter for the given number of keys with the given false positive rate (below 0.5):
+    // k = -log2 p hash functions and m = k n / ln 2 bits (at least 64), the optimal size for k.
+    pub fn new(keys: usize, false_positive_rate: f64) -> BloomFilter {
+        let rate = false_positive_rate.clamp(f64::MIN_POSITIVE, 0.5);
+        let hashes = (-rate.log2()).round().clamp(1.0, 32.0);
+        let bits = (hashes * keys.max(1) as f64 / 2f64.ln()).ceil().max(64.0);
+        BloomFilter {
+            bits: vec![0; (bits as usize).div_ceil(64)],
+            hashes: hashes as u32,
+            false_positive_rate: rate,
+        }
+    }
+
+    // the rate the filter was sized for.
+    pub fn false_positive_rate(&self) -> f64 {
+        self.false_positive_rate
+    }
+
+    // the size of the filter in bits.
+    pub fn bits(&self) -> usize {
+        self.bits.len() * 64
+    }
+
+    // the number of hash functions.
+    pub fn hashes(&self) -> u32 {
+        self.hashes
+    }
+
+    // the bits of a key: the i-th hash function hashes i with the key. double hashing
+    // (h1 + i * h2) is cheaper, but in a small filter two keys then often share all their bits.
+    // the hasher has fixed keys, so a key always sets the same bits.
+    fn positions<'k>(&self, key: &'k [Value]) -> impl Iterator<Item = usize> + 'k {
+        let bits = self.bits() as u64;
+        (0..self.hashes).map(move |i| {
+            let mut hasher = DefaultHasher::new();
+            i.hash(&mut hasher);
+            key.hash(&mut hasher);
+            (hasher.finish() % bits) as usize
+        })
+    }
+
+    pub fn insert(&mut self, key: &[Value]) {
+        for position in self.positions(key).collect::<Vec<_>>() {
+            self.bits[position / 64] |= 1 << (position % 64);
+        }
+    }
+
+    // false when the key was never inserted, true when it may have been.
+    pub fn contains(&self, key: &[Value]) -> bool {
+        self.positions(key)
+            .all(|position| self.bits[position / 64] & (1 << (position % 64)) != 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inserted_keys_are_found() {
+        let mut filter = BloomFilter::new(1000, 0.01);
+        for i in 0..1000 {
+            filter.insert(&[Value::Int64(i), Value::Utf8(i.to_string())]);
+        }
+        assert!((0..1000).all(|i| filter.contains(&[Value::Int64(i), Value::Utf8(i.to_string())])));
+    }
+
+    #[test]
+    fn false_positives_stay_near_the_rate() {
+        for (keys, rate) in [(1, 0.01), (100, 0.01), (10_000, 0.01), (10_000, 0.0001)] {
+            let mut filter = BloomFilter::new(keys, rate);
+            for i in 0..keys as i64 {
+                filter.insert(&[Value::Int64(i)]);
+            }
+            let probes = 100_000;
+            let false_positives = (0..probes)
+                .filter(|i| filter.contains(&[Value::Int64(1_000_000_000 + i)]))
+                .count();
+            assert!(
+                (false_positives as f64) < 2.0 * rate * probes as f64,
+                "{} keys at rate {}: {} false positives",
+                keys,
+                rate,
+                false_positives
+            );
+        }
+    }
+}
@@ -0,0 +1,239 @@
+// Coercion of join keys:
+// relations that are loaded from independent files can infer different types
+// for the same join variable (e.g. brew_id as Int64 in one CSV and Utf8 in another).
+// Before the semijoins run, every join variable gets one common type in all relations.
+// Int64 is widened to Float64, which holds every integer up to 2^53 exactly; a larger key would
+// be rounded and could equal a different key, so it is an error instead.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::Arc;
+
+use arrow::{
+    array::{ArrayRef, Int64Array},
+    compute::cast,
+    datatypes::{DataType, Field},
+    record_batch::RecordBatch,
+};
+
+use crate::join::make_record_batch;
+
+// how strict the planner is about join variables with different types.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KeyCoercion {
+    // different types for a join variable are an error.
+    Strict,
+    // Int64 and Float64 are widened to Float64, other mismatches are an error
+    // (e.g. Int64 and Utf8).
+    #[default]
+    Numeric,
+    // like Numeric, but any other mismatch is coerced to Utf8.
+    Lenient,
+}
+
+// every integer up to 2^53 is exact as a Float64.
+const MAX_EXACT_FLOAT: i64 = 1 << 53;
+
+// the common type of two key types, None if the coercion does not allow it.
+pub fn common_type(type1: &DataType, type2: &DataType, coercion: KeyCoercion) -> Option<DataType> {
+    if type1 == type2 {
+        return Some(type1.clone());
+    }
+    let numeric = |data_type: &DataType| matches!(data_type, DataType::Int64 | DataType::Float64);
+    match coercion {
+        KeyCoercion::Strict => None,
+        KeyCoercion::Numeric if numeric(type1) && numeric(type2) => Some(DataType::Float64),
+        KeyCoercion::Numeric => None,
+        KeyCoercion::Lenient if numeric(type1) && numeric(type2) => Some(DataType::Float64),
+        KeyCoercion::Lenient => Some(DataType::Utf8),
+    }
+}
+
+// cast a key column to its common type. an Int64 key beyond 2^53 is an error when it is widened
+// to Float64: it would be rounded and could equal a different key.
+pub fn cast_key(column: &ArrayRef, data_type: &DataType) -> Result<ArrayRef, Box<dyn Error>> {
+    if column.data_type() == &DataType::Int64 && data_type == &DataType::Float64 {
+        let values = column.as_any().downcast_ref::<Int64Array>().unwrap();
+        if let Some(value) = values
+            .iter()
+            .flatten()
+            .find(|value| value.unsigned_abs() > MAX_EXACT_FLOAT as u64)
+        {
+            return Err(format!("Key {} is too large to compare as Float64", value).into());
+        }
+    }
+    Ok(cast(column, data_type)?)
+}
+
+// give every variable the same type in all relations it appears in.
+// the relations are bound to their atom: every column is named after its variable.
+pub fn coerce_join_keys(
+    relations: &mut HashMap<String, RecordBatch>,
+    coercion: KeyCoercion,
+) -> Result<(), Box<dyn Error>> {
+    // find the common type of every variable.
+    let mut key_types: HashMap<String, (DataType, String)> = HashMap::new();
+    let mut names: Vec<&String> = relations.keys().collect();
+    names.sort();
+    for name in names {
+        for field in relations[name].schema().fields() {
+            let data_type = field.data_type();
+            let entry = key_types
+                .entry(field.name().clone())
+                .or_insert_with(|| (data_type.clone(), name.clone()));
+            match common_type(&entry.0, data_type, coercion) {
+                Some(common) => entry.0 = common,
+                None => {
+                    return Err(format!(
+                        "Join variable {} has type {:?} in {} and type {:?} in {}",
+                        field.name(),
+                        entry.0,
+                        entry.1,
+                        data_type,
+                        name
+                    )
+                    .into())
+                }
+            }
+        }
+    }
+
+    // cast the columns that do not have the common type yet.
+    for relation in relations.values_mut() {
+        let schema = relation.schema();
+        if schema
+            .fields()
+            .iter()
+            .all(|field| field.data_type() == &key_types[field.name()].0)
+        {
+            continue;
+        }
+        let mut fields = Vec::new();
+        let mut columns = Vec::new();
+        for (field, column) in schema.fields().iter().zip(relation.columns()) {
+            let data_type = &key_types[field.name()].0;
+            fields.push(Field::new(field.name(), data_type.clone(), true));
+            if column.data_type() == data_type {
+                columns.push(Arc::clone(column));
+            } else {
+                columns.push(cast_key(column, data_type)?);
+            }
+        }
+        *relation = make_record_batch(fields, columns, relation.num_rows());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Array, Float64Array, StringArray};
+
+    // a relation with one key column.
+    fn relation(variable: &str, column: ArrayRef) -> RecordBatch {
+        let rows = column.len();
+        let field = Field::new(variable, column.data_type().clone(), true);
+        make_record_batch(vec![field], vec![column], rows)
+    }
+
+    #[test]
+    fn common_type_of_each_coercion() {
+        let (int, float, string) = (DataType::Int64, DataType::Float64, DataType::Utf8);
+        for coercion in [
+            KeyCoercion::Strict,
+            KeyCoercion::Numeric,
+            KeyCoercion::Lenient,
+        ] {
+            assert_eq!(common_type(&int, &int, coercion), Some(int.clone()));
+            assert_eq!(
+                common_type(&string, &string, coercion),
+                Some(string.clone())
+            );
+        }
+        assert_eq!(common_type(&int, &float, KeyCoercion::Strict), None);
+        assert_eq!(common_type(&int, &string, KeyCoercion::Strict), None);
+        assert_eq!(
+            common_type(&int, &float, KeyCoercion::Numeric),
+            Some(float.clone())
+        );
+        assert_eq!(common_type(&int, &string, KeyCoercion::Numeric), None);
+        assert_eq!(
+            common_type(&float, &int, KeyCoercion::Lenient),
+            Some(float.clone())
+        );
+        assert_eq!(
+            common_type(&int, &string, KeyCoercion::Lenient),
+            Some(string.clone())
+        );
+    }
+
+    #[test]
+    fn join_keys_get_the_common_type() {
+        let relations = || -> HashMap<String, RecordBatch> {
+            [
+                (
+                    "R".to_string(),
+                    relation("a", Arc::new(Int64Array::from(vec![1, 2]))),
+                ),
+                (
+                    "S".to_string(),
+                    relation("a", Arc::new(Float64Array::from(vec![1.0, 2.5]))),
+                ),
+            ]
+            .into()
+        };
+        let mut strict = relations();
+        assert!(coerce_join_keys(&mut strict, KeyCoercion::Strict).is_err());
+        let mut numeric = relations();
+        coerce_join_keys(&mut numeric, KeyCoercion::Numeric).unwrap();
+        let keys = numeric["R"]
+            .column(0)
+            .as_any()
+            .downcast_ref::<Float64Array>()
+            .unwrap();
+        assert_eq!(keys, &Float64Array::from(vec![1.0, 2.0]));
+
+        let mut mixed = relations();
+        mixed.insert(
+            "T".to_string(),
+            relation("a", Arc::new(StringArray::from(vec!["1"]))),
+        );
+        assert!(coerce_join_keys(&mut mixed.clone(), KeyCoercion::Numeric).is_err());
+        coerce_join_keys(&mut mixed, KeyCoercion::Lenient).unwrap();
+        assert!(mixed
+            .values()
+            .all(|relation| relation.schema().field(0).data_type() == &DataType::Utf8));
+    }
+
+    #[test]
+    fn large_integer_keys_are_not_rounded() {
+        // 2^53 + 1 rounds to 2^53 as a Float64.
+        let relations = |largest: i64| -> HashMap<String, RecordBatch> {
+            [
+                (
+                    "R".to_string(),
+                    relation("a", Arc::new(Int64Array::from(vec![1, largest]))),
+                ),
+                (
+                    "S".to_string(),
+                    relation("a", Arc::new(Float64Array::from(vec![1.0]))),
+                ),
+            ]
+            .into()
+        };
+        let mut exact = relations(MAX_EXACT_FLOAT);
+        coerce_join_keys(&mut exact, KeyCoercion::Numeric).unwrap();
+        for largest in [MAX_EXACT_FLOAT + 1, -MAX_EXACT_FLOAT - 1] {
+            for coercion in [KeyCoercion::Numeric, KeyCoercion::Lenient] {
+                assert!(coerce_join_keys(&mut relations(largest), coercion).is_err());
+            }
+        }
+        // without a Float64 key the integers are not cast.
+        let mut integers: HashMap<String, RecordBatch> = [(
+            "R".to_string(),
+            relation("a", Arc::new(Int64Array::from(vec![i64::MAX]))),
+        )]
+        .into();
+        coerce_join_keys(&mut integers, KeyCoercion::Numeric).unwrap();
+    }
+}
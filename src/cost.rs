@@ -0,0 +1,204 @@
+// Cost estimates for choosing the join tree of an acyclic query.
+// A join tree is estimated with the rows of the views of the atoms (after the selections) and
+// the number of distinct values of their variables: a semijoin keeps the part of the target
+// whose key is in the source, and a join is the product of the sizes divided by the larger
+// number of distinct keys (keys are assumed uniform and contained in each other).
+
+use std::collections::{HashMap, HashSet};
+
+use arrow::record_batch::RecordBatch;
+
+use crate::hypergraph::Hypergraph;
+use crate::join::{value_at, Value};
+use crate::jointrees::Selections;
+use crate::queries::ConjunctiveQuery;
+
+// the cardinalities of the views of a query. without statistics every relation has one row,
+// so every join tree costs the same.
+#[derive(Debug, Clone, Default)]
+pub struct Statistics {
+    rows: HashMap<String, f64>,
+    // the number of distinct values of a variable in a relation.
+    distinct: HashMap<(String, String), f64>,
+}
+
+impl Statistics {
+    // the statistics of the bound relations of a query (a column per variable), of their
+    // selected rows. the distinct values are only counted for the join variables and the head
+    // variables.
+    pub fn new(
+        query: &ConjunctiveQuery,
+        relations: &HashMap<String, RecordBatch>,
+        selections: &Selections,
+    ) -> Statistics {
+        let hypergraph = Hypergraph::from_query(query);
+        let head = query.head_atom.variables();
+        let mut statistics = Statistics::default();
+        for (name, relation) in relations {
+            let rows: Vec<usize> = match selections.get(name) {
+                Some(rows) => rows.iter().map(|&row| row as usize).collect(),
+                None => (0..relation.num_rows()).collect(),
+            };
+            statistics.rows.insert(name.clone(), rows.len() as f64);
+            for (index, field) in relation.schema().fields().iter().enumerate() {
+                let variable = field.name().as_str();
+                let joined = hypergraph
+                    .vertex(variable)
+                    .is_some_and(|v| hypergraph.edges_of(v).len() > 1);
+                if !joined && !head.contains(&variable) {
+                    continue;
+                }
+                let column = relation.column(index);
+                let values: HashSet<Value> =
+                    rows.iter().map(|&row| value_at(column, row)).collect();
+                statistics
+                    .distinct
+                    .insert((name.clone(), field.name().clone()), values.len() as f64);
+            }
+        }
+        statistics
+    }
+
+    pub fn rows(&self, relation: &str) -> f64 {
+        self.rows.get(relation).copied().unwrap_or(1.0)
+    }
+
+    // the number of distinct values of some variables of a relation, at most its rows.
+    pub fn distinct(&self, relation: &str, variables: &[&str]) -> f64 {
+        let rows = self.rows(relation);
+        variables
+            .iter()
+            .map(|v| {
+                self.distinct
+                    .get(&(relation.to_string(), v.to_string()))
+                    .copied()
+                    .unwrap_or(rows)
+            })
+            .product::<f64>()
+            .min(rows)
+            .max(1.0)
+    }
+}
+
+// the edges of a join tree with a parent before its children: the reverse of the post order
+// with the children in order, which is the order of the top-down pass.
+pub fn top_down_order(parents: &[Option<usize>], root: usize) -> Vec<usize> {
+    let mut children = vec![Vec::new(); parents.len()];
+    for (child, parent) in parents.iter().enumerate() {
+        if let Some(parent) = parent {
+            children[*parent].push(child);
+        }
+    }
+    // the post order without recursion: every edge with its next child on the stack.
+    let mut order = Vec::new();
+    let mut stack = vec![(root, 0)];
+    while let Some((e, next)) = stack.pop() {
+        match children[e].get(next) {
+            Some(&child) => {
+                stack.push((e, next + 1));
+                stack.push((child, 0));
+            }
+            None => order.push(e),
+        }
+    }
+    order.reverse();
+    order
+}
+
+// the estimated cost of evaluating a component of a query on one of its join trees: the rows
+// read by the semijoins of both passes and the rows made by the joins of the final phase,
+// where after every join only the head variables and the variables still needed are kept.
+// a boolean query stops after the bottom-up pass.
+pub fn join_tree_cost(
+    hypergraph: &Hypergraph,
+    parents: &[Option<usize>],
+    root: usize,
+    head: &[&str],
+    statistics: &Statistics,
+) -> f64 {
+    let name = |e: usize| hypergraph.edges[e].name;
+    let variables = |e: usize| -> Vec<&'static str> {
+        hypergraph.edges[e]
+            .vertices
+            .iter()
+            .map(|&v| hypergraph.vertices[v])
+            .collect()
+    };
+    let key = |e: usize, f: usize| -> Vec<&'static str> {
+        let other = variables(f);
+        variables(e)
+            .into_iter()
+            .filter(|v| other.contains(v))
+            .collect()
+    };
+    let order = top_down_order(parents, root);
+    let mut rows: Vec<f64> = (0..parents.len())
+        .map(|e| statistics.rows(name(e)))
+        .collect();
+    // the distinct keys of a relation shrink with its rows.
+    let distinct = |e: usize, key: &[&str], rows: &[f64]| {
+        statistics.distinct(name(e), key).min(rows[e]).max(1.0)
+    };
+
+    let mut cost = 0.0;
+    for &child in order.iter().rev() {
+        let Some(parent) = parents[child] else {
+            continue;
+        };
+        let key = key(parent, child);
+        cost += rows[parent] + rows[child];
+        let kept = distinct(child, &key, &rows) / distinct(parent, &key, &rows);
+        rows[parent] *= kept.min(1.0);
+    }
+    if head.is_empty() {
+        return cost;
+    }
+    for &child in &order {
+        let Some(parent) = parents[child] else {
+            continue;
+        };
+        let key = key(parent, child);
+        cost += rows[parent] + rows[child];
+        let kept = distinct(parent, &key, &rows) / distinct(child, &key, &rows);
+        rows[child] *= kept.min(1.0);
+    }
+
+    // the last position of every variable in the order: after it the variable is not needed,
+    // unless it is in the head.
+    let mut last: HashMap<&str, usize> = HashMap::new();
+    for (position, &e) in order.iter().enumerate() {
+        for variable in variables(e) {
+            last.insert(variable, position);
+        }
+    }
+    // the fewest distinct values of every variable in the joined relations.
+    let mut values: HashMap<&str, f64> = HashMap::new();
+    let add_values = |e: usize, values: &mut HashMap<&str, f64>| {
+        for variable in variables(e) {
+            let distinct = distinct(e, &[variable], &rows);
+            let entry = values.entry(variable).or_insert(distinct);
+            *entry = entry.min(distinct);
+        }
+    };
+    add_values(root, &mut values);
+
+    let mut size = rows[root];
+    let mut joined = variables(root);
+    for (position, &child) in order.iter().enumerate().skip(1) {
+        let parent = parents[child].unwrap();
+        let key = key(parent, child);
+        size *= rows[child] / distinct(parent, &key, &rows).max(distinct(child, &key, &rows));
+        cost += size;
+        add_values(child, &mut values);
+        for variable in variables(child) {
+            if !joined.contains(&variable) {
+                joined.push(variable);
+            }
+        }
+        // the projection on the variables that are still needed.
+        joined.retain(|v| last[v] > position || head.contains(v));
+        let product: f64 = joined.iter().map(|v| values[v]).product();
+        size = size.min(product);
+    }
+    cost
+}
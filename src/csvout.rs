@@ -11,5 +11,7 @@ pub fn write_record_batch_to_csv(record_batch: &RecordBatch, filename: &str) {
     let buffered_file = BufWriter::new(file);
 
     let mut writer = Writer::new(buffered_file);
-    writer.write(record_batch);
+    writer
+        .write(record_batch)
+        .expect("Unable to write record batch");
 }
\ No newline at end of file
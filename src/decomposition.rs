@@ -0,0 +1,291 @@
+// Generalized hypertree decompositions:
+// a cyclic query has no join tree, but its variables can be grouped into bags that do form
+// a tree (every variable appears in a connected subtree of bags, every atom fits in a bag).
+// Each bag is covered by atoms; the relation of a bag is the join of its covering atoms.
+// The bags then form an acyclic query that the semijoin reducer and the join phase can run on.
+// The width (the most atoms needed to cover a bag) bounds the size of the bag relations.
+
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::hypergraph::Hypergraph;
+use crate::jointrees::{Direction, KeyColumn, SemiJoinProgram, SemiJoinStep};
+use crate::queries::ConjunctiveQuery;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bag {
+    pub name: String,
+    pub variables: Vec<&'static str>,
+    // the atoms (index in the body) whose join covers the variables of the bag.
+    pub cover: Vec<usize>,
+    // the atoms that are enforced in this bag: all their variables are in the bag.
+    pub atoms: Vec<usize>,
+    pub parent: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Decomposition {
+    pub bags: Vec<Bag>,
+}
+
+impl Decomposition {
+    // the width: the largest number of atoms needed to cover a bag.
+    pub fn width(&self) -> usize {
+        self.bags.iter().map(|bag| bag.cover.len()).max().unwrap_or(0)
+    }
+
+    // the bags in post order: children before their parent.
+    fn post_order(&self) -> Vec<usize> {
+        let mut order = Vec::new();
+        for (index, bag) in self.bags.iter().enumerate() {
+            if bag.parent.is_none() {
+                self.visit(index, &mut order);
+            }
+        }
+        order
+    }
+
+    fn visit(&self, index: usize, order: &mut Vec<usize>) {
+        for (child, bag) in self.bags.iter().enumerate() {
+            if bag.parent == Some(index) {
+                self.visit(child, order);
+            }
+        }
+        order.push(index);
+    }
+
+    // the semijoin program of the full reducer over the bag tree.
+    // the columns of a bag relation are its variables, in order.
+    pub fn semi_join_program(&self) -> SemiJoinProgram {
+        let mut steps = Vec::new();
+        for index in self.post_order() {
+            let bag = &self.bags[index];
+            let Some(parent) = bag.parent.map(|parent| &self.bags[parent]) else {
+                continue;
+            };
+            let keys = parent
+                .variables
+                .iter()
+                .enumerate()
+                .filter_map(|(target_column, variable)| {
+                    let source_column = bag.variables.iter().position(|v| v == variable)?;
+                    Some(KeyColumn {
+                        variable: variable.to_string(),
+                        target_column,
+                        source_column,
+                    })
+                })
+                .collect();
+            steps.push(SemiJoinStep {
+                target: parent.name.clone(),
+                source: bag.name.clone(),
+                keys,
+                direction: Direction::BottomUp,
+            });
+        }
+        let roots = self
+            .bags
+            .iter()
+            .filter(|bag| bag.parent.is_none())
+            .map(|bag| bag.name.clone())
+            .collect();
+        SemiJoinProgram::new(roots, steps)
+    }
+}
+
+impl fmt::Display for Decomposition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "decomposition of width {}:", self.width())?;
+        for bag in &self.bags {
+            let parent = match bag.parent {
+                Some(parent) => self.bags[parent].name.clone(),
+                None => "-".to_string(),
+            };
+            writeln!(
+                f,
+                "  {} {{{}}} cover {:?} atoms {:?} parent {}",
+                bag.name,
+                bag.variables.join(", "),
+                bag.cover,
+                bag.atoms,
+                parent
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// the bags of a tree decomposition, made by eliminating the variables from the primal graph
+// (variables are adjacent if they appear in the same atom) in min-fill order.
+// returns the bags and the parent of every bag.
+fn eliminate(query: &ConjunctiveQuery) -> (Vec<HashSet<&'static str>>, Vec<Option<usize>>) {
+    let hypergraph = Hypergraph::from_query(query);
+    let variables = &hypergraph.vertices;
+    let mut neighbours: Vec<HashSet<usize>> = hypergraph
+        .primal_graph()
+        .into_iter()
+        .map(|n| n.into_iter().collect())
+        .collect();
+
+    let mut eliminated = vec![false; variables.len()];
+    let mut order: Vec<usize> = Vec::new();
+    let mut bags: Vec<HashSet<usize>> = Vec::new();
+    for _ in 0..variables.len() {
+        // the variable whose elimination adds the fewest fill edges (then the lowest degree).
+        let fill = |v: usize| {
+            let n: Vec<&usize> = neighbours[v].iter().collect();
+            let mut missing = 0;
+            for (a, i) in n.iter().enumerate() {
+                for j in &n[a + 1..] {
+                    if !neighbours[**i].contains(j) {
+                        missing += 1;
+                    }
+                }
+            }
+            (missing, n.len())
+        };
+        let v = (0..variables.len())
+            .filter(|&v| !eliminated[v])
+            .min_by_key(|&v| (fill(v), v))
+            .unwrap();
+        let mut bag: HashSet<usize> = neighbours[v].clone();
+        bag.insert(v);
+        // connect the neighbours and remove v.
+        let n: Vec<usize> = neighbours[v].iter().copied().collect();
+        for &i in &n {
+            for &j in &n {
+                if i != j {
+                    neighbours[i].insert(j);
+                }
+            }
+            neighbours[i].remove(&v);
+        }
+        eliminated[v] = true;
+        order.push(v);
+        bags.push(bag);
+    }
+
+    // the parent of a bag is the bag of the neighbour that is eliminated first after v.
+    let position = |v: usize| order.iter().position(|&w| w == v).unwrap();
+    let parents: Vec<Option<usize>> = (0..bags.len())
+        .map(|b| {
+            bags[b]
+                .iter()
+                .filter(|&&v| v != order[b])
+                .map(|&v| position(v))
+                .min()
+        })
+        .collect();
+    let bags = bags
+        .into_iter()
+        .map(|bag| bag.into_iter().map(|v| variables[v]).collect())
+        .collect();
+    (bags, parents)
+}
+
+// compute a generalized hypertree decomposition of the query.
+pub fn decompose(query: &ConjunctiveQuery) -> Decomposition {
+    let (bags, mut parents) = eliminate(query);
+
+    // contract a bag into its parent (or a child into the bag) when it is contained in it.
+    let mut removed = vec![false; bags.len()];
+    loop {
+        let contract = (0..bags.len()).find_map(|b| {
+            let parent = parents[b]?;
+            if removed[b] || !bags[b].is_subset(&bags[parent]) {
+                return None;
+            }
+            Some((b, parent))
+        });
+        let contract = contract.or_else(|| {
+            (0..bags.len()).find_map(|b| {
+                let parent = parents[b]?;
+                if removed[b] || !bags[parent].is_subset(&bags[b]) {
+                    return None;
+                }
+                Some((parent, b))
+            })
+        });
+        let Some((from, into)) = contract else {
+            break;
+        };
+        // into takes the place of from in the tree.
+        for (b, parent) in parents.iter_mut().enumerate() {
+            if *parent == Some(from) && b != into {
+                *parent = Some(into);
+            }
+        }
+        if parents[into] == Some(from) {
+            parents[into] = parents[from];
+        }
+        removed[from] = true;
+        parents[from] = None;
+    }
+
+    // a disconnected query gives a forest, hang the other trees under the first root.
+    let kept: Vec<usize> = (0..bags.len()).filter(|&b| !removed[b]).collect();
+    let roots: Vec<usize> = kept.iter().copied().filter(|&b| parents[b].is_none()).collect();
+    for &root in roots.iter().skip(1) {
+        parents[root] = Some(roots[0]);
+    }
+
+    // renumber the kept bags and cover them with atoms.
+    let mut result: Vec<Bag> = Vec::new();
+    let mut atoms_left: Vec<usize> = (0..query.body_atoms.len()).collect();
+    let query_variables = Hypergraph::from_query(query).vertices;
+    for (index, &b) in kept.iter().enumerate() {
+        let variables: Vec<&'static str> = query_variables
+            .iter()
+            .copied()
+            .filter(|v| bags[b].contains(v))
+            .collect();
+        // greedy cover: take the atom with the most uncovered variables (then the fewest others).
+        let mut uncovered: HashSet<&'static str> = bags[b].clone();
+        let mut cover: Vec<usize> = Vec::new();
+        while !uncovered.is_empty() {
+            let best = (0..query.body_atoms.len())
+                .max_by_key(|&a| {
+                    let atom_variables = query.body_atoms[a].variables();
+                    let new = atom_variables.iter().filter(|v| uncovered.contains(*v)).count();
+                    let other = atom_variables.iter().filter(|v| !bags[b].contains(*v)).count();
+                    (new, usize::MAX - other, usize::MAX - a)
+                })
+                .unwrap();
+            for variable in query.body_atoms[best].variables() {
+                uncovered.remove(variable);
+            }
+            cover.push(best);
+        }
+        // enforce every atom in the first bag that contains all its variables.
+        let atoms: Vec<usize> = atoms_left
+            .iter()
+            .copied()
+            .filter(|&a| {
+                query.body_atoms[a]
+                    .variables()
+                    .iter()
+                    .all(|v| bags[b].contains(v))
+            })
+            .collect();
+        atoms_left.retain(|a| !atoms.contains(a));
+        result.push(Bag {
+            name: format!("bag{}", index),
+            variables,
+            cover,
+            atoms,
+            parent: parents[b].map(|parent| kept.iter().position(|&k| k == parent).unwrap()),
+        });
+    }
+    // atoms without variables fit in any bag (a query without variables gets one empty bag).
+    if result.is_empty() {
+        result.push(Bag {
+            name: "bag0".to_string(),
+            variables: Vec::new(),
+            cover: Vec::new(),
+            atoms: Vec::new(),
+            parent: None,
+        });
+    }
+    result[0].atoms.extend(atoms_left);
+    Decomposition { bags: result }
+}
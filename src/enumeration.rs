@@ -0,0 +1,189 @@
+// Constant delay enumeration of the answers of a free-connex acyclic query.
+// After the full reducer every tuple of every relation is part of an answer, so the answers
+// are the join of the relations projected on the head variables, and for a free-connex query
+// these projections form an acyclic query. Its join forest is walked nested loop style with
+// a hash index per relation: every lookup finds a matching tuple, so the next answer is found
+// after a constant number of steps and no join is ever materialized.
+
+use std::collections::HashMap;
+
+use arrow::record_batch::RecordBatch;
+
+use crate::gyo::join_forest;
+use crate::hypergraph::{Hyperedge, Hypergraph};
+use crate::join::{distinct, project, row_key, value_at, NullPolicy, Value};
+use crate::queries::ConjunctiveQuery;
+
+// one relation of the nested loop.
+struct Level {
+    relation: RecordBatch,
+    // the position in the answer of every column of the relation.
+    slots: Vec<usize>,
+    // the positions bound by the levels before, the key of the index.
+    key: Vec<usize>,
+    index: HashMap<Vec<Value>, Vec<usize>>,
+    // the key of the current rows and the position in them.
+    cursor: (Vec<Value>, usize),
+}
+
+// an iterator over the answers of a query, in head order.
+pub struct Enumeration {
+    levels: Vec<Level>,
+    binding: Vec<Value>,
+    started: bool,
+    done: bool,
+}
+
+impl Enumeration {
+    // the relations are the fully reduced views of the atoms of a free-connex query.
+    pub fn new(
+        query: &ConjunctiveQuery,
+        relations: &HashMap<String, RecordBatch>,
+        null_policy: NullPolicy,
+    ) -> Enumeration {
+        let head = query.head_atom.variables();
+        let mut edges: Vec<Hyperedge> = Vec::new();
+        let mut projections: Vec<RecordBatch> = Vec::new();
+        for atom in &query.body_atoms {
+            let variables: Vec<&str> = atom
+                .variables()
+                .into_iter()
+                .filter(|v| head.contains(v))
+                .collect();
+            if variables.is_empty() {
+                continue;
+            }
+            let names: Vec<String> = variables.iter().map(|v| v.to_string()).collect();
+            projections.push(distinct(&project(&relations[atom.name], &names)));
+            edges.push(Hyperedge {
+                name: atom.name,
+                vertices: variables
+                    .iter()
+                    .map(|v| head.iter().position(|w| w == v).unwrap())
+                    .collect(),
+            });
+        }
+        let hypergraph = Hypergraph::new(head.clone(), edges);
+        let edges = &hypergraph.edges;
+        let parents = join_forest(&hypergraph)
+            .expect("the head projections of a free-connex query are acyclic");
+
+        // the levels in pre order of the join forest, so a parent comes before its children.
+        let mut order: Vec<usize> = Vec::new();
+        let mut stack: Vec<usize> = (0..edges.len())
+            .rev()
+            .filter(|&e| parents[e].is_none())
+            .collect();
+        while let Some(e) = stack.pop() {
+            order.push(e);
+            stack.extend((0..edges.len()).rev().filter(|&c| parents[c] == Some(e)));
+        }
+        let mut bound = vec![false; head.len()];
+        let mut levels = Vec::new();
+        for e in order {
+            let relation = projections[e].clone();
+            let slots = edges[e].vertices.clone();
+            let key_columns: Vec<usize> = (0..slots.len()).filter(|&c| bound[slots[c]]).collect();
+            let columns: Vec<_> = key_columns
+                .iter()
+                .map(|&c| relation.column(c).clone())
+                .collect();
+            let mut index: HashMap<Vec<Value>, Vec<usize>> = HashMap::new();
+            for row in 0..relation.num_rows() {
+                if let Some(key) = row_key(&columns, row, null_policy) {
+                    index.entry(key).or_default().push(row);
+                }
+            }
+            for &slot in &slots {
+                bound[slot] = true;
+            }
+            levels.push(Level {
+                key: key_columns.iter().map(|&c| slots[c]).collect(),
+                relation,
+                slots,
+                index,
+                cursor: (Vec::new(), 0),
+            });
+        }
+        Enumeration {
+            levels,
+            binding: vec![Value::Null; head.len()],
+            started: false,
+            done: relations.values().any(|relation| relation.num_rows() == 0),
+        }
+    }
+
+    // bind the first row of a level that matches the levels before it.
+    fn open(&mut self, level: usize) -> bool {
+        let key: Vec<Value> = self.levels[level]
+            .key
+            .iter()
+            .map(|&slot| self.binding[slot].clone())
+            .collect();
+        let first = match self.levels[level].index.get(&key) {
+            Some(rows) => rows[0],
+            None => return false,
+        };
+        self.levels[level].cursor = (key, 0);
+        self.bind(level, first);
+        true
+    }
+
+    // bind the next matching row of a level.
+    fn advance(&mut self, level: usize) -> bool {
+        let Level { index, cursor, .. } = &mut self.levels[level];
+        let rows = &index[&cursor.0];
+        if cursor.1 + 1 == rows.len() {
+            return false;
+        }
+        cursor.1 += 1;
+        let row = rows[cursor.1];
+        self.bind(level, row);
+        true
+    }
+
+    fn bind(&mut self, level: usize, row: usize) {
+        let level = &self.levels[level];
+        for (column, &slot) in level.slots.iter().enumerate() {
+            self.binding[slot] = value_at(level.relation.column(column), row);
+        }
+    }
+}
+
+impl Iterator for Enumeration {
+    type Item = Vec<Value>;
+
+    fn next(&mut self) -> Option<Vec<Value>> {
+        if self.done {
+            return None;
+        }
+        // the first answer opens every level, the next ones advance the last level that can.
+        let (mut level, mut opening) = match self.started {
+            false => (0, true),
+            true if self.levels.is_empty() => (0, false),
+            true => (self.levels.len() - 1, false),
+        };
+        self.started = true;
+        loop {
+            if opening {
+                if level == self.levels.len() {
+                    return Some(self.binding.clone());
+                }
+                if self.open(level) {
+                    level += 1;
+                    continue;
+                }
+                opening = false;
+            } else if level < self.levels.len() && self.advance(level) {
+                opening = true;
+                level += 1;
+                continue;
+            }
+            if level == 0 {
+                self.done = true;
+                return None;
+            }
+            level -= 1;
+        }
+    }
+}
@@ -0,0 +1,239 @@
+// Explain: what happened during the evaluation of a query.
+// For every phase of yannakakis the row counts and the elapsed time are kept,
+// so an empty or slow result can be traced back to a selection, a semijoin or a join.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Instant;
+
+use serde::Serialize;
+
+use crate::bloom::BloomFilterPass;
+use crate::jointrees::{Direction, SemiJoinProgram, SemiJoinStep};
+
+// the selections of one atom (constants and repeated variables).
+#[derive(Debug, Clone, Serialize)]
+pub struct SelectionReport {
+    pub relation: String,
+    pub predicates: Vec<String>,
+    pub input_rows: usize,
+    pub output_rows: usize,
+    pub elapsed_us: u128,
+}
+
+// the bloom filter pre-pass of a semijoin step: the filter of the source keys and the target
+// rows it passed, of which the false positives were dropped by the exact semijoin.
+#[derive(Debug, Clone, Serialize)]
+pub struct BloomFilterReport {
+    pub false_positive_rate: f64,
+    pub bits: usize,
+    pub hashes: u32,
+    pub passed_rows: usize,
+    pub false_positives: usize,
+}
+
+// one executed semijoin step.
+#[derive(Debug, Clone, Serialize)]
+pub struct SemiJoinReport {
+    pub step: SemiJoinStep,
+    pub input_rows: usize,
+    pub source_rows: usize,
+    pub output_rows: usize,
+    pub bloom_filter: Option<BloomFilterReport>,
+    pub elapsed_us: u128,
+}
+
+// the materialization of a bag of a decomposition (cyclic queries).
+#[derive(Debug, Clone, Serialize)]
+pub struct BagReport {
+    pub bag: String,
+    pub variables: Vec<String>,
+    pub atoms: Vec<String>,
+    pub output_rows: usize,
+    pub elapsed_us: u128,
+}
+
+// the evaluation of a cyclic query with generic join.
+#[derive(Debug, Clone, Serialize)]
+pub struct GenericJoinReport {
+    pub variable_order: Vec<String>,
+    pub output_rows: usize,
+    pub elapsed_us: u128,
+}
+
+// one join of the final phase: the result so far joined with a relation,
+// or for a cross product with the answers of the component of the relation.
+#[derive(Debug, Clone, Serialize)]
+pub struct JoinReport {
+    pub relation: String,
+    pub left_rows: usize,
+    pub right_rows: usize,
+    pub output_rows: usize,
+    pub cross_product: bool,
+    pub elapsed_us: u128,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Explain {
+    pub query: String,
+    pub program: SemiJoinProgram,
+    // the width of the decomposition, for cyclic queries.
+    pub width: Option<usize>,
+    pub selections: Vec<SelectionReport>,
+    pub bags: Vec<BagReport>,
+    pub generic_join: Option<GenericJoinReport>,
+    pub semijoins: Vec<SemiJoinReport>,
+    // the bloom filter pass of the options, also when no source was large enough for it.
+    pub bloom_filter: Option<BloomFilterPass>,
+    pub joins: Vec<JoinReport>,
+    pub answer_rows: usize,
+    pub elapsed_us: u128,
+}
+
+impl Explain {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+}
+
+// microseconds since start.
+pub fn elapsed_us(start: Instant) -> u128 {
+    start.elapsed().as_micros()
+}
+
+// write the join tree of a semijoin program as an indented tree:
+// the children of a relation are the sources of its bottom-up steps.
+fn write_tree(
+    f: &mut fmt::Formatter,
+    relation: &str,
+    children: &HashMap<&str, Vec<&SemiJoinStep>>,
+    depth: usize,
+) -> fmt::Result {
+    for step in children.get(relation).into_iter().flatten() {
+        let keys: Vec<&str> = step.keys.iter().map(|key| key.variable.as_str()).collect();
+        writeln!(
+            f,
+            "{}{} [{}]",
+            "  ".repeat(depth + 1),
+            step.source,
+            keys.join(", ")
+        )?;
+        write_tree(f, &step.source, children, depth + 1)?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for Explain {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "explain {}", self.query)?;
+
+        let bottom_up = self.program.pass(Direction::BottomUp);
+        let mut children: HashMap<&str, Vec<&SemiJoinStep>> = HashMap::new();
+        for step in &bottom_up {
+            children.entry(step.target.as_str()).or_default().push(step);
+        }
+        if !self.program.roots.is_empty() {
+            writeln!(f, "join tree:")?;
+        }
+        for root in &self.program.roots {
+            writeln!(f, "  {}", root)?;
+            write_tree(f, root, &children, 1)?;
+        }
+
+        writeln!(f, "selections:")?;
+        for selection in &self.selections {
+            let predicates = match selection.predicates.is_empty() {
+                true => "-".to_string(),
+                false => selection.predicates.join(" and "),
+            };
+            writeln!(
+                f,
+                "  {:<12} {:>8} -> {:>8} rows {:>8} us  {}",
+                selection.relation,
+                selection.input_rows,
+                selection.output_rows,
+                selection.elapsed_us,
+                predicates
+            )?;
+        }
+        if let Some(width) = self.width {
+            writeln!(f, "bags (width {}):", width)?;
+        }
+        for bag in &self.bags {
+            writeln!(
+                f,
+                "  {:<12} {:>8} rows {:>8} us  {{{}}} = ⋈ {}",
+                bag.bag,
+                bag.output_rows,
+                bag.elapsed_us,
+                bag.variables.join(", "),
+                bag.atoms.join(" ⋈ ")
+            )?;
+        }
+        if let Some(generic_join) = &self.generic_join {
+            writeln!(f, "generic join:")?;
+            writeln!(
+                f,
+                "  {:>8} rows {:>8} us  variable order {}",
+                generic_join.output_rows,
+                generic_join.elapsed_us,
+                generic_join.variable_order.join(", ")
+            )?;
+        }
+        writeln!(f, "semijoins:")?;
+        for semijoin in &self.semijoins {
+            writeln!(
+                f,
+                "  {:>8} -> {:>8} rows ({:>8} in source) {:>8} us  {}",
+                semijoin.input_rows,
+                semijoin.output_rows,
+                semijoin.source_rows,
+                semijoin.elapsed_us,
+                semijoin.step
+            )?;
+            if let Some(bloom) = &semijoin.bloom_filter {
+                // the observed rate: the dangling rows the filter passed.
+                let dangling = semijoin.input_rows - semijoin.output_rows;
+                writeln!(
+                    f,
+                    "  {:>8} -> {:>8} rows bloom filter ({} bits, {} hashes, rate {}): \
+                     {} false positives ({:.4} observed)",
+                    semijoin.input_rows,
+                    bloom.passed_rows,
+                    bloom.bits,
+                    bloom.hashes,
+                    bloom.false_positive_rate,
+                    bloom.false_positives,
+                    bloom.false_positives as f64 / dangling.max(1) as f64
+                )?;
+            }
+        }
+        if let Some(pass) = &self.bloom_filter {
+            if self.semijoins.iter().all(|s| s.bloom_filter.is_none()) {
+                writeln!(
+                    f,
+                    "  bloom filter not used: no source has {} selected rows",
+                    pass.min_source_rows
+                )?;
+            }
+        }
+        writeln!(f, "joins:")?;
+        for join in &self.joins {
+            writeln!(
+                f,
+                "  {} {:<12} {:>8} x {:>8} -> {:>8} rows {:>8} us",
+                if join.cross_product { "×" } else { "⋈" },
+                join.relation,
+                join.left_rows,
+                join.right_rows,
+                join.output_rows,
+                join.elapsed_us
+            )?;
+        }
+        writeln!(
+            f,
+            "answer: {} rows in {} us",
+            self.answer_rows, self.elapsed_us
+        )
+    }
+}
@@ -15,60 +15,369 @@ Repeatedly apply the follwowing operations (in no particular order):
 Gyo reduction is performed on the body atoms of the conjunctive query.
 */
 
-use crate::queries::{ConjunctiveQuery, Term};
-use std::collections::HashSet;
+use crate::decomposition::decompose;
+use crate::hypergraph::{Hyperedge, Hypergraph};
+use crate::queries::ConjunctiveQuery;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+
+// a hyperedge: the name of the atom and its (remaining) variables.
+pub type Ear = (&'static str, Vec<&'static str>);
+
+// a cycle that prevents acyclicity: atoms[i] and atoms[i + 1] share variables[i],
+// the last atom and the first atom share the last variable.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CycleWitness {
+    pub atoms: Vec<&'static str>,
+    pub variables: Vec<&'static str>,
+}
+
+// abc –b– bef –e– ce –c– abc
+impl fmt::Display for CycleWitness {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (atom, variable) in self.atoms.iter().zip(&self.variables) {
+            write!(f, "{} –{}– ", atom, variable)?;
+        }
+        write!(f, "{}", self.atoms[0])
+    }
+}
 
 // function to test if a conjunctive qury is acyclic
-pub fn acyclic_test(query: &ConjunctiveQuery) {
+// also prints the widths of the query and, when the sizes of all its relations are known,
+// the agm bound on the size of the join of its body.
+pub fn acyclic_test(query: &ConjunctiveQuery, sizes: &HashMap<String, usize>) {
+    let forest = acyclic_join_forest(query);
+    // the gyo reduction and maximum cardinality search must give the same verdict.
+    debug_assert_eq!(forest.is_some(), gyo_reduce(query).is_empty());
+    if forest.is_some() {
+        println!("acyclic");
+        println!("  class: {}", classify(query));
+        println!("  free-connex: {}", free_connex(query));
+    } else {
+        println!("cyclic");
+        let core = gyo_reduce(query);
+        // tell why: what is left after the reduction and a cycle in it.
+        let core_edges: Vec<String> = core
+            .iter()
+            .map(|(name, terms)| {
+                let terms: Vec<String> = terms.iter().map(|term| term.to_string()).collect();
+                format!("{}{{{}}}", name, terms.join(", "))
+            })
+            .collect();
+        println!("  core: {}", core_edges.join(" "));
+        if let Some(cycle) = cycle_witness(&core) {
+            println!("  cycle: {}", cycle);
+        }
+    }
+    println!("  widths: {}", widths(query));
+    if let Some(bound) = agm_bound(query, sizes) {
+        println!("  agm bound: {:.0}", bound);
+    }
+}
+
+// the acyclicity classes of a hypergraph, every class is contained in the one before:
+// berge-acyclic => gamma-acyclic => beta-acyclic => alpha-acyclic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Acyclicity {
+    Cyclic,
+    Alpha,
+    Beta,
+    Gamma,
+    Berge,
+}
+
+impl fmt::Display for Acyclicity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Acyclicity::Cyclic => "cyclic",
+            Acyclicity::Alpha => "alpha-acyclic",
+            Acyclicity::Beta => "beta-acyclic",
+            Acyclicity::Gamma => "gamma-acyclic",
+            Acyclicity::Berge => "berge-acyclic",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// the strongest acyclicity class the query belongs to.
+pub fn classify(query: &ConjunctiveQuery) -> Acyclicity {
+    let hypergraph = Hypergraph::from_query(query);
+    let edges: Vec<BTreeSet<usize>> = hypergraph
+        .edges
+        .iter()
+        .map(|edge| edge.vertices.iter().copied().collect())
+        .collect();
+    if join_forest(&hypergraph).is_none() {
+        Acyclicity::Cyclic
+    } else if !beta_acyclic(edges.clone()) {
+        Acyclicity::Alpha
+    } else if !gamma_acyclic(edges.clone()) {
+        Acyclicity::Beta
+    } else if !berge_acyclic(hypergraph.vertices.len(), &edges) {
+        Acyclicity::Gamma
+    } else {
+        Acyclicity::Berge
+    }
+}
+
+// beta-acyclic: every subset of the edges is alpha-acyclic.
+// repeatedly remove a nest point, a vertex whose edges are ordered by inclusion;
+// the hypergraph is beta-acyclic when every vertex can be removed.
+fn beta_acyclic(mut edges: Vec<BTreeSet<usize>>) -> bool {
+    loop {
+        let vertices: BTreeSet<usize> = edges.iter().flatten().copied().collect();
+        if vertices.is_empty() {
+            return true;
+        }
+        let nest_point = vertices.into_iter().find(|v| {
+            let mut containing: Vec<&BTreeSet<usize>> =
+                edges.iter().filter(|edge| edge.contains(v)).collect();
+            containing.sort_by_key(|edge| edge.len());
+            containing.windows(2).all(|pair| pair[0].is_subset(pair[1]))
+        });
+        let Some(nest_point) = nest_point else {
+            return false;
+        };
+        for edge in edges.iter_mut() {
+            edge.remove(&nest_point);
+        }
+    }
+}
+
+// gamma-acyclic: apply the reductions of fagin until nothing changes, the hypergraph
+// is gamma-acyclic when nothing is left:
+// 1. delete a vertex that is in one edge only.
+// 2. delete an edge with at most one vertex.
+// 3. delete an edge that is equal to another edge.
+// 4. delete a vertex that is in exactly the same edges as another vertex.
+fn gamma_acyclic(mut edges: Vec<BTreeSet<usize>>) -> bool {
+    loop {
+        let before = edges.clone();
+        let vertices: BTreeSet<usize> = edges.iter().flatten().copied().collect();
+        let edges_of = |v: usize, edges: &[BTreeSet<usize>]| -> Vec<usize> {
+            (0..edges.len())
+                .filter(|&e| edges[e].contains(&v))
+                .collect()
+        };
+        for &v in &vertices {
+            if edges_of(v, &edges).len() == 1 {
+                for edge in edges.iter_mut() {
+                    edge.remove(&v);
+                }
+            }
+        }
+        edges.retain(|edge| edge.len() > 1);
+        let mut distinct: Vec<BTreeSet<usize>> = Vec::new();
+        for edge in edges {
+            if !distinct.contains(&edge) {
+                distinct.push(edge);
+            }
+        }
+        edges = distinct;
+        let vertices: Vec<usize> = edges
+            .iter()
+            .flatten()
+            .copied()
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        if let Some(v) = vertices.iter().enumerate().find_map(|(i, &v)| {
+            vertices[i + 1..]
+                .iter()
+                .any(|&u| edges_of(u, &edges) == edges_of(v, &edges))
+                .then_some(v)
+        }) {
+            for edge in edges.iter_mut() {
+                edge.remove(&v);
+            }
+        }
+        if edges.is_empty() {
+            return true;
+        }
+        if edges == before {
+            return false;
+        }
+    }
+}
+
+// berge-acyclic: the incidence graph (vertices and edges, a vertex is connected to the edges
+// that contain it) has no cycle, so no two edges share more than one vertex.
+fn berge_acyclic(vertices: usize, edges: &[BTreeSet<usize>]) -> bool {
+    // union find over the vertices followed by the edges.
+    let mut parent: Vec<usize> = (0..vertices + edges.len()).collect();
+    fn find(parent: &mut [usize], mut node: usize) -> usize {
+        while parent[node] != node {
+            parent[node] = parent[parent[node]];
+            node = parent[node];
+        }
+        node
+    }
+    for (e, edge) in edges.iter().enumerate() {
+        for &v in edge {
+            let (a, b) = (find(&mut parent, v), find(&mut parent, vertices + e));
+            if a == b {
+                return false;
+            }
+            parent[a] = b;
+        }
+    }
+    true
+}
+
+// the join forest of the body atoms (the parent of every atom), None if the query is cyclic.
+pub fn acyclic_join_forest(query: &ConjunctiveQuery) -> Option<Vec<Option<usize>>> {
+    join_forest(&Hypergraph::from_query(query))
+}
+
+// a query is free-connex if it is acyclic and stays acyclic when its head variables are
+// added as one more hyperedge. the answers of such a query can be enumerated with constant delay.
+pub fn free_connex(query: &ConjunctiveQuery) -> bool {
+    let hypergraph = Hypergraph::from_query(query);
+    if join_forest(&hypergraph).is_none() {
+        return false;
+    }
+    let head = query
+        .head_atom
+        .variables()
+        .iter()
+        .filter_map(|v| hypergraph.vertex(v))
+        .collect();
+    let mut edges = hypergraph.edges.clone();
+    edges.push(Hyperedge {
+        name: query.head_atom.name,
+        vertices: head,
+    });
+    join_forest(&Hypergraph::new(hypergraph.vertices, edges)).is_some()
+}
+
+// maximum cardinality search (tarjan and yannakakis), linear in the size of the hypergraph.
+// the edges are chosen one by one, always one with the most vertices seen before. the seen
+// vertices of an edge must all be in the edge that saw the last of them first, which becomes
+// its parent; an edge without seen vertices starts a new tree.
+// returns the parent of every edge (a join forest), or None when the hypergraph is cyclic.
+pub fn join_forest(hypergraph: &Hypergraph) -> Option<Vec<Option<usize>>> {
+    let vertices = hypergraph.vertices.len();
+    let edges: Vec<&[usize]> = hypergraph
+        .edges
+        .iter()
+        .map(|edge| edge.vertices.as_slice())
+        .collect();
+    // the edges not chosen yet by their number of seen vertices. an edge moves up a bucket
+    // when one of its vertices is seen, the old entry is skipped when it comes up.
+    let largest = edges.iter().map(|edge| edge.len()).max().unwrap_or(0);
+    let mut buckets: Vec<Vec<usize>> = vec![Vec::new(); largest + 1];
+    buckets[0] = (0..edges.len()).rev().collect();
+    let mut seen = vec![0; edges.len()];
+    let mut chosen = vec![false; edges.len()];
+    // the position (in the order) of the edge that saw a vertex first.
+    let mut seen_by: Vec<usize> = vec![usize::MAX; vertices];
+    let mut order: Vec<usize> = Vec::new();
+    let mut best = 0;
+    while order.len() < edges.len() {
+        let e = loop {
+            match buckets[best].pop() {
+                Some(e) if !chosen[e] && seen[e] == best => break e,
+                Some(_) => continue,
+                None => best -= 1,
+            }
+        };
+        chosen[e] = true;
+        for &v in edges[e] {
+            if seen_by[v] != usize::MAX {
+                continue;
+            }
+            seen_by[v] = order.len();
+            for &f in hypergraph.edges_of(v) {
+                if !chosen[f] {
+                    seen[f] += 1;
+                    buckets[seen[f]].push(f);
+                    best = best.max(seen[f]);
+                }
+            }
+        }
+        order.push(e);
+    }
+
+    let mut position = vec![0; edges.len()];
+    for (i, &e) in order.iter().enumerate() {
+        position[e] = i;
+    }
+    let mut parents = vec![None; edges.len()];
+    let mut children: Vec<Vec<usize>> = vec![Vec::new(); edges.len()];
+    for &e in &order {
+        parents[e] = edges[e]
+            .iter()
+            .map(|&v| seen_by[v])
+            .filter(|&p| p < position[e])
+            .max()
+            .map(|p| order[p]);
+        if let Some(parent) = parents[e] {
+            children[parent].push(e);
+        }
+    }
+    // check the seen vertices of the children of every edge, marking its vertices once.
+    let mut mark = vec![usize::MAX; vertices];
+    for (parent, children) in children.iter().enumerate() {
+        for &v in edges[parent] {
+            mark[v] = parent;
+        }
+        for &child in children {
+            let contained = edges[child]
+                .iter()
+                .all(|&v| seen_by[v] >= position[child] || mark[v] == parent);
+            if !contained {
+                return None;
+            }
+        }
+    }
+    Some(parents)
+}
+
+// the gyo reduction of the body atoms, returns the irreducible core that is left
+// (empty if the query is acyclic).
+pub fn gyo_reduce(query: &ConjunctiveQuery) -> Vec<Ear> {
     // make mutable vector containing all ears.
-    let mut ears = collect_ears(&query);
+    let mut ears = collect_ears(query);
     let mut modified = true;
     // loop untill the conjuctive qury is empty or nothing can be roved anymore
     while modified {
         let ears_clone = ears.clone(); // Make a clone to check for modifications
         remove_unique_items(&mut ears);
-        remove_single_item_vectors(&mut ears);
-        
+        remove_contained_ears(&mut ears);
+
         // check if modifications were made
         modified = ears != ears_clone;
     }
-
-    if ears.iter().any(|vector| !vector.is_empty()) {
-        println!("cyclic");
-    } else {
-        println!("acyclic");
-    }
+    ears.retain(|(_, vector)| !vector.is_empty());
+    ears
 }
-// collect all ears of the conjunctive query
-fn collect_ears(query: &ConjunctiveQuery) -> Vec<Vec<&Term>> {
-    // initialize a vector to store the terms vectors
-    let mut ears: Vec<Vec<&Term>> = Vec::new();
 
-    // iterate through body atoms
-    for body_atom in &query.body_atoms {
-        let terms_vector: Vec<&Term> = body_atom.terms.clone();
-        ears.push(terms_vector);
-    }
-
-    // return the collected ears vector
-    ears
+// collect all ears of the conjunctive query: the hyperedges of its hypergraph
+fn collect_ears(query: &ConjunctiveQuery) -> Vec<Ear> {
+    let hypergraph = Hypergraph::from_query(query);
+    hypergraph
+        .edges
+        .iter()
+        .map(|edge| {
+            let variables = edge.vertices.iter().map(|&v| hypergraph.vertices[v]);
+            (edge.name, variables.collect())
+        })
+        .collect()
 }
 // remove all items unique to there ear(vector).
-fn remove_unique_items(vectors: &mut Vec<Vec<&Term>>) {
+fn remove_unique_items(vectors: &mut [Ear]) {
     // create a HashSet for each vector
-    let mut unique_items: Vec<HashSet<Term>> = vectors.iter().map(|_| HashSet::new()).collect();
+    let mut unique_items: Vec<HashSet<&str>> = vectors.iter().map(|_| HashSet::new()).collect();
 
     // iterate through all vectors to populate and update the HashSet
-    for (vector_index, vector) in vectors.iter().enumerate() {
+    for (vector_index, (_, vector)) in vectors.iter().enumerate() {
         for item in vector {
-            // Clone the item to insert it into the HashSet
-            let cloned_item = item.clone();
-            unique_items[vector_index].insert(cloned_item.clone());
+            unique_items[vector_index].insert(item);
         }
     }
 
     // iterate through each vector and remove items that are unique to that vector
-    for (vector_index, vector) in vectors.iter_mut().enumerate() {
+    for (vector_index, (_, vector)) in vectors.iter_mut().enumerate() {
         vector.retain(|item| {
             unique_items
                 .iter()
@@ -80,7 +389,659 @@ fn remove_unique_items(vectors: &mut Vec<Vec<&Term>>) {
     }
 }
 
-// do not keep ears existing of one element
-fn remove_single_item_vectors(vectors: &mut Vec<Vec<&Term>>) {
-    vectors.retain(|vector| vector.len() > 1);
+// remove the ears that are contained in another ear, of equal ears the first one stays.
+// this also removes the ears left with a single (shared) element.
+fn remove_contained_ears(vectors: &mut Vec<Ear>) {
+    let mut index = 0;
+    while index < vectors.len() {
+        let vector = &vectors[index].1;
+        let contained = vectors
+            .iter()
+            .enumerate()
+            .any(|(other, (_, other_vector))| {
+                other != index
+                    && vector.iter().all(|item| other_vector.contains(item))
+                    && (other_vector.len() > vector.len() || other < index)
+            });
+        if contained {
+            vectors.remove(index);
+        } else {
+            index += 1;
+        }
+    }
+}
+
+// find a cycle in the core left by the gyo reduction.
+// the shortest cycle is returned whose variables are not all in one hyperedge:
+// such a cycle (like abc –b– bef –e– ce –c– abc) can not be reduced,
+// while a cycle covered by one hyperedge (like abc –b– bc –c– abc) can.
+pub fn cycle_witness(core: &[Ear]) -> Option<CycleWitness> {
+    for length in 2..=core.len() {
+        for start in 0..core.len() {
+            let mut atoms = vec![start];
+            let mut variables = Vec::new();
+            if let Some(cycle) = extend_cycle(core, length, &mut atoms, &mut variables) {
+                return Some(cycle);
+            }
+        }
+    }
+    None
+}
+
+// depth first search for a cycle of length atoms that starts with atoms[0].
+fn extend_cycle(
+    core: &[Ear],
+    length: usize,
+    atoms: &mut Vec<usize>,
+    variables: &mut Vec<&'static str>,
+) -> Option<CycleWitness> {
+    let last = *atoms.last().unwrap();
+    if atoms.len() == length {
+        // close the cycle with a new variable shared by the last and the first atom.
+        for variable in shared_terms(&core[last].1, &core[atoms[0]].1) {
+            if variables.contains(&variable) {
+                continue;
+            }
+            variables.push(variable);
+            let covered = core
+                .iter()
+                .any(|(_, terms)| variables.iter().all(|v| terms.contains(v)));
+            if !covered {
+                return Some(CycleWitness {
+                    atoms: atoms.iter().map(|&index| core[index].0).collect(),
+                    variables: variables.clone(),
+                });
+            }
+            variables.pop();
+        }
+        return None;
+    }
+    // only continue with atoms after the first, so every cycle is found from its first atom.
+    for next in atoms[0] + 1..core.len() {
+        if atoms.contains(&next) {
+            continue;
+        }
+        for variable in shared_terms(&core[last].1, &core[next].1) {
+            if variables.contains(&variable) {
+                continue;
+            }
+            atoms.push(next);
+            variables.push(variable);
+            if let Some(cycle) = extend_cycle(core, length, atoms, variables) {
+                return Some(cycle);
+            }
+            atoms.pop();
+            variables.pop();
+        }
+    }
+    None
+}
+
+// the terms two hyperedges have in common.
+fn shared_terms(terms1: &[&'static str], terms2: &[&'static str]) -> Vec<&'static str> {
+    let mut shared: Vec<&'static str> = Vec::new();
+    for term in terms1 {
+        if terms2.contains(term) && !shared.contains(term) {
+            shared.push(term);
+        }
+    }
+    shared
+}
+
+// the hypergraph of the query in graphviz dot format.
+// the atoms (hyperedges) are boxes connected to the variables (vertices) they contain.
+pub fn hypergraph_to_dot(query: &ConjunctiveQuery) -> String {
+    let hypergraph = Hypergraph::from_query(query);
+    let mut dot = String::from("graph hypergraph {\n");
+    for variable in &hypergraph.vertices {
+        dot.push_str(&format!(
+            "    \"v_{}\" [label=\"{}\", shape=ellipse];\n",
+            variable, variable
+        ));
+    }
+    for (index, edge) in hypergraph.edges.iter().enumerate() {
+        dot.push_str(&format!(
+            "    e{} [label=\"{}\", shape=box];\n",
+            index, edge.name
+        ));
+        for &v in &edge.vertices {
+            dot.push_str(&format!(
+                "    e{} -- \"v_{}\";\n",
+                index, hypergraph.vertices[v]
+            ));
+        }
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+// the widths are exact up to this many join variables in a part.
+const EXACT_VARIABLES: usize = 16;
+
+// the widths of the hypergraph of a query, all 1 for an acyclic query.
+// the widths are exact for queries with at most EXACT_VARIABLES join variables in every
+// connected part, otherwise they are estimates from a min-fill decomposition (the ghw and fhw
+// are upper bounds).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Widths {
+    pub hypertree: usize,
+    pub generalized: usize,
+    pub fractional: f64,
+    pub exact: bool,
+}
+
+// hw 2, ghw 2, fhw 1.50
+impl fmt::Display for Widths {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "hw {}, ghw {}, fhw {:.2}",
+            self.hypertree, self.generalized, self.fractional
+        )?;
+        if !self.exact {
+            write!(f, " (estimates from a decomposition)")?;
+        }
+        Ok(())
+    }
+}
+
+// compute the hypertree width, generalized hypertree width and fractional hypertree width.
+pub fn widths(query: &ConjunctiveQuery) -> Widths {
+    let hypergraph = Hypergraph::from_query(query);
+    if hypergraph.vertices.is_empty() {
+        return Widths {
+            hypertree: 0,
+            generalized: 0,
+            fractional: 0.0,
+            exact: true,
+        };
+    }
+    if join_forest(&hypergraph).is_some() {
+        return Widths {
+            hypertree: 1,
+            generalized: 1,
+            fractional: 1.0,
+            exact: true,
+        };
+    }
+    let ones = vec![1.0; hypergraph.edges.len()];
+
+    // a variable in one atom only can be eliminated first, its bag is covered by its atom.
+    // the rest falls apart in connected parts, the widths are the largest of the parts.
+    let joined: Vec<usize> = (0..hypergraph.vertices.len())
+        .filter(|&v| hypergraph.edges_of(v).len() > 1)
+        .collect();
+    let core = hypergraph.induced(&joined);
+    let parts: Vec<Hypergraph> = core
+        .components()
+        .iter()
+        .map(|component| {
+            let mut vertices: Vec<usize> = component
+                .iter()
+                .flat_map(|&e| core.edges[e].vertices.iter().copied())
+                .collect();
+            vertices.sort_unstable();
+            vertices.dedup();
+            core.induced(&vertices)
+        })
+        .collect();
+    if parts
+        .iter()
+        .all(|part| part.vertices.len() <= EXACT_VARIABLES)
+    {
+        let hypertree = (1..=hypergraph.edges.len())
+            .find(|&k| hypertree_decomposable(&hypergraph, k))
+            .unwrap();
+        let mut generalized = 1;
+        let mut fractional: f64 = 1.0;
+        for part in &parts {
+            let vertices: Vec<usize> = (0..part.vertices.len()).collect();
+            let ones = vec![1.0; part.edges.len()];
+            let part_generalized =
+                elimination_width(&vertices, part, |bag| edge_cover(bag, part) as f64);
+            let part_fractional = elimination_width(&vertices, part, |bag| {
+                fractional_edge_cover(bag, part, &ones)
+            });
+            generalized = generalized.max(part_generalized as usize);
+            fractional = fractional.max(part_fractional);
+        }
+        return Widths {
+            hypertree,
+            generalized,
+            fractional,
+            exact: true,
+        };
+    }
+
+    // too many variables to try every elimination order or separator: use the width of the
+    // min-fill decomposition for the hypertree and generalized widths.
+    let decomposition = decompose(query);
+    let fractional = decomposition
+        .bags
+        .iter()
+        .map(|bag| {
+            let bag: Vec<usize> = bag
+                .variables
+                .iter()
+                .map(|v| hypergraph.vertex(v).unwrap())
+                .collect();
+            fractional_edge_cover(&bag, &hypergraph, &ones)
+        })
+        .fold(1.0, f64::max);
+    let generalized = decomposition.width();
+    Widths {
+        hypertree: generalized,
+        generalized,
+        fractional: fractional.min(generalized as f64),
+        exact: false,
+    }
+}
+
+// the agm bound: the largest possible size of the join of the body atoms,
+// prod |R_e|^x_e for the fractional edge cover x that minimizes sum x_e log |R_e|.
+// None when the size of a relation is not known.
+pub fn agm_bound(query: &ConjunctiveQuery, sizes: &HashMap<String, usize>) -> Option<f64> {
+    let hypergraph = Hypergraph::from_query(query);
+    let sizes: Vec<usize> = query
+        .body_atoms
+        .iter()
+        .map(|atom| sizes.get(atom.name).copied())
+        .collect::<Option<_>>()?;
+    if sizes.contains(&0) {
+        return Some(0.0);
+    }
+    let costs: Vec<f64> = sizes.iter().map(|&size| (size as f64).ln()).collect();
+    let vertices: Vec<usize> = (0..hypergraph.vertices.len()).collect();
+    Some(fractional_edge_cover(&vertices, &hypergraph, &costs).exp())
+}
+
+// the smallest width over all orders in which the vertices can be eliminated
+// from the primal graph; the width of an order is the largest cost of the bags it makes.
+// dynamic programming over the sets of vertices that are eliminated first.
+fn elimination_width(
+    vertices: &[usize],
+    hypergraph: &Hypergraph,
+    cost: impl Fn(&[usize]) -> f64,
+) -> f64 {
+    let n = vertices.len();
+    // neighbours in the primal graph as bit sets over the vertices.
+    let primal = hypergraph.primal_graph();
+    let mut neighbours = vec![0usize; n];
+    for i in 0..n {
+        for j in 0..n {
+            if primal[vertices[i]].contains(&vertices[j]) {
+                neighbours[i] |= 1 << j;
+            }
+        }
+    }
+    let mut costs: HashMap<usize, f64> = HashMap::new();
+    let mut best = vec![f64::INFINITY; 1 << n];
+    best[0] = 0.0;
+    for set in 1..1usize << n {
+        for v in (0..n).filter(|v| set & 1 << v != 0) {
+            let rest = set & !(1 << v);
+            if best[rest] >= best[set] {
+                continue;
+            }
+            // the bag of v: v and the vertices left that v reaches through eliminated ones.
+            let mut reached = neighbours[v];
+            let mut through = reached & rest;
+            while through != 0 {
+                let u = through.trailing_zeros() as usize;
+                through &= !(1 << u);
+                let new = neighbours[u] & !reached & !(1 << v);
+                reached |= new;
+                through |= new & rest;
+            }
+            let bag = reached & !set | 1 << v;
+            let bag_cost = *costs.entry(bag).or_insert_with(|| {
+                let bag: Vec<usize> = (0..n)
+                    .filter(|i| bag & 1 << i != 0)
+                    .map(|i| vertices[i])
+                    .collect();
+                cost(&bag)
+            });
+            best[set] = best[set].min(best[rest].max(bag_cost));
+        }
+    }
+    best[(1 << n) - 1]
+}
+
+// the edge cover number: the fewest edges that together contain the vertices.
+fn edge_cover(vertices: &[usize], hypergraph: &Hypergraph) -> usize {
+    (0..=vertices.len())
+        .find(|&k| covered_by(vertices, hypergraph, k))
+        .unwrap()
+}
+
+// can k edges cover the vertices? one of them contains the first vertex.
+fn covered_by(vertices: &[usize], hypergraph: &Hypergraph, k: usize) -> bool {
+    let Some(&first) = vertices.first() else {
+        return true;
+    };
+    k > 0
+        && hypergraph.edges_of(first).iter().any(|&e| {
+            let edge = &hypergraph.edges[e].vertices;
+            let rest: Vec<usize> = vertices
+                .iter()
+                .copied()
+                .filter(|v| !edge.contains(v))
+                .collect();
+            covered_by(&rest, hypergraph, k - 1)
+        })
+}
+
+// the fractional edge cover number: the smallest sum cost_e * x_e over weights x_e >= 0
+// such that the edges that contain a vertex weigh at least 1 together, for every vertex.
+// solved as the dual packing problem (the origin is feasible) with the simplex method,
+// bland's rule keeps it from cycling.
+fn fractional_edge_cover(vertices: &[usize], hypergraph: &Hypergraph, costs: &[f64]) -> f64 {
+    const EPSILON: f64 = 1e-9;
+    let n = vertices.len();
+    let m = hypergraph.edges.len();
+    // one row per edge: sum of y_v for v in the edge + slack = cost of the edge.
+    let mut rows: Vec<Vec<f64>> = hypergraph
+        .edges
+        .iter()
+        .enumerate()
+        .map(|(e, edge)| {
+            let mut row = vec![0.0; n + m + 1];
+            for (i, v) in vertices.iter().enumerate() {
+                if edge.vertices.contains(v) {
+                    row[i] = 1.0;
+                }
+            }
+            row[n + e] = 1.0;
+            row[n + m] = costs[e];
+            row
+        })
+        .collect();
+    // maximize the sum of y_v.
+    let mut objective = vec![0.0; n + m + 1];
+    for value in objective.iter_mut().take(n) {
+        *value = -1.0;
+    }
+    let mut basis: Vec<usize> = (n..n + m).collect();
+    while let Some(column) = (0..n + m).find(|&j| objective[j] < -EPSILON) {
+        let Some(row) = (0..m)
+            .filter(|&i| rows[i][column] > EPSILON)
+            .min_by(|&a, &b| {
+                let ratio_a = rows[a][n + m] / rows[a][column];
+                let ratio_b = rows[b][n + m] / rows[b][column];
+                ratio_a
+                    .partial_cmp(&ratio_b)
+                    .unwrap()
+                    .then(basis[a].cmp(&basis[b]))
+            })
+        else {
+            // a vertex that is in no edge can not be covered.
+            return f64::INFINITY;
+        };
+        let pivot = rows[row][column];
+        for value in rows[row].iter_mut() {
+            *value /= pivot;
+        }
+        let pivot_row = rows[row].clone();
+        for (i, other) in rows.iter_mut().enumerate() {
+            let factor = other[column];
+            if i != row && factor != 0.0 {
+                for (value, p) in other.iter_mut().zip(&pivot_row) {
+                    *value -= factor * p;
+                }
+            }
+        }
+        let factor = objective[column];
+        for (value, p) in objective.iter_mut().zip(&pivot_row) {
+            *value -= factor * p;
+        }
+        basis[row] = column;
+    }
+    objective[n + m]
+}
+
+// det-k-decomp: does the hypergraph have a hypertree decomposition of width at most k?
+fn hypertree_decomposable(hypergraph: &Hypergraph, k: usize) -> bool {
+    let component: BTreeSet<usize> = (0..hypergraph.vertices.len()).collect();
+    decomposable(
+        &component,
+        &BTreeSet::new(),
+        hypergraph,
+        k,
+        &mut HashSet::new(),
+    )
+}
+
+// can the component (vertices) be decomposed below a bag it shares connection with?
+// a separator of at most k edges covers the connection and part of the component,
+// the bag is what it covers of both, and every component left below the bag must decompose.
+fn decomposable(
+    component: &BTreeSet<usize>,
+    connection: &BTreeSet<usize>,
+    hypergraph: &Hypergraph,
+    k: usize,
+    failed: &mut HashSet<(BTreeSet<usize>, BTreeSet<usize>)>,
+) -> bool {
+    let key = (component.clone(), connection.clone());
+    if failed.contains(&key) {
+        return false;
+    }
+    let edges: Vec<&[usize]> = hypergraph
+        .edges
+        .iter()
+        .map(|edge| edge.vertices.as_slice())
+        .collect();
+    let candidates: Vec<usize> = (0..edges.len())
+        .filter(|&e| {
+            edges[e]
+                .iter()
+                .any(|v| component.contains(v) || connection.contains(v))
+        })
+        .collect();
+    for size in 1..=k.min(candidates.len()) {
+        for separator in combinations(&candidates, size) {
+            let covered: BTreeSet<usize> = separator
+                .iter()
+                .flat_map(|&e| edges[e].iter().copied())
+                .collect();
+            if !connection.is_subset(&covered) || covered.is_disjoint(component) {
+                continue;
+            }
+            let bag: BTreeSet<usize> = covered
+                .iter()
+                .copied()
+                .filter(|v| component.contains(v) || connection.contains(v))
+                .collect();
+            let left: BTreeSet<usize> = component.difference(&bag).copied().collect();
+            let decomposes = components(&left, hypergraph).iter().all(|child| {
+                // the bag vertices in the edges that touch the child.
+                let child_connection: BTreeSet<usize> = edges
+                    .iter()
+                    .filter(|edge| edge.iter().any(|v| child.contains(v)))
+                    .flat_map(|edge| edge.iter().copied())
+                    .filter(|v| bag.contains(v))
+                    .collect();
+                decomposable(child, &child_connection, hypergraph, k, failed)
+            });
+            if decomposes {
+                return true;
+            }
+        }
+    }
+    failed.insert(key);
+    false
+}
+
+// the connected components of the vertices, two vertices are connected if they share an edge.
+fn components(vertices: &BTreeSet<usize>, hypergraph: &Hypergraph) -> Vec<BTreeSet<usize>> {
+    let mut components: Vec<BTreeSet<usize>> = Vec::new();
+    for &start in vertices {
+        if components
+            .iter()
+            .any(|component| component.contains(&start))
+        {
+            continue;
+        }
+        let mut component = BTreeSet::from([start]);
+        let mut stack = vec![start];
+        while let Some(v) = stack.pop() {
+            for &e in hypergraph.edges_of(v) {
+                for &u in &hypergraph.edges[e].vertices {
+                    if vertices.contains(&u) && component.insert(u) {
+                        stack.push(u);
+                    }
+                }
+            }
+        }
+        components.push(component);
+    }
+    components
+}
+
+// all subsets of size k of the items, in order, one at a time.
+fn combinations(items: &[usize], k: usize) -> impl Iterator<Item = Vec<usize>> + '_ {
+    let n = items.len();
+    let first = (k <= n).then(|| (0..k).collect::<Vec<usize>>());
+    std::iter::successors(first, move |indices| {
+        // move the last index that can move, the ones after it follow it.
+        let i = (0..k).rev().find(|&i| indices[i] < n - k + i)?;
+        let mut next = indices.clone();
+        next[i] += 1;
+        for j in i + 1..k {
+            next[j] = next[j - 1] + 1;
+        }
+        Some(next)
+    })
+    .map(move |indices| indices.iter().map(|&i| items[i]).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cost::Statistics;
+    use crate::jointrees::{build_join_forest, validate_join_forest};
+    use crate::queries::{
+        create_containment_example_query, create_cyclic_example_query,
+        create_equal_atoms_example_query, Atom, Term,
+    };
+
+    // a boolean query over atoms of variables.
+    fn query(atoms: &[(&'static str, &[&'static str])]) -> ConjunctiveQuery {
+        let body_atoms = atoms
+            .iter()
+            .map(|&(name, variables)| Atom {
+                name,
+                terms: variables
+                    .iter()
+                    .map(|&v| &*Box::leak(Box::new(Term::Variable(v))))
+                    .collect(),
+            })
+            .collect();
+        ConjunctiveQuery {
+            head_atom: Atom {
+                name: "Answer",
+                terms: vec![],
+            },
+            body_atoms,
+        }
+    }
+
+    // removing the variables of a single ear changes nothing, only the containment rule applies.
+    fn assert_only_containment_applies(query: &ConjunctiveQuery) {
+        let mut ears = collect_ears(query);
+        let before = ears.clone();
+        remove_unique_items(&mut ears);
+        assert_eq!(ears, before);
+    }
+
+    #[test]
+    fn containment_reduces_contained_atoms() {
+        let query = create_containment_example_query();
+        assert_only_containment_applies(&query);
+        assert!(gyo_reduce(&query).is_empty());
+        assert!(acyclic_join_forest(&query).is_some());
+    }
+
+    #[test]
+    fn containment_reduces_equal_atoms() {
+        let query = create_equal_atoms_example_query();
+        assert_only_containment_applies(&query);
+        assert!(gyo_reduce(&query).is_empty());
+        assert!(acyclic_join_forest(&query).is_some());
+    }
+
+    #[test]
+    fn cyclic_query_keeps_a_core() {
+        let query = create_cyclic_example_query();
+        assert!(!gyo_reduce(&query).is_empty());
+        assert!(acyclic_join_forest(&query).is_none());
+    }
+
+    #[test]
+    fn join_forest_exists_when_gyo_reduces_everything() {
+        let acyclic = [
+            query(&[("R", &["a", "b"]), ("S", &["b", "c"]), ("T", &["c", "d"])]),
+            query(&[("R", &["a", "b"]), ("S", &["a", "c"]), ("T", &["a", "d"])]),
+            query(&[
+                ("R", &["a", "b", "c"]),
+                ("S", &["a", "b"]),
+                ("T", &["b", "c"]),
+            ]),
+            query(&[("R", &["a", "b"]), ("S", &["c", "d"]), ("T", &["d", "e"])]),
+            create_containment_example_query(),
+            create_equal_atoms_example_query(),
+        ];
+        let cyclic = [
+            query(&[("R", &["a", "b"]), ("S", &["b", "c"]), ("T", &["c", "a"])]),
+            query(&[
+                ("R", &["a", "b"]),
+                ("S", &["b", "c"]),
+                ("T", &["c", "d"]),
+                ("U", &["d", "a"]),
+            ]),
+            query(&[
+                ("R", &["a", "b"]),
+                ("S", &["b", "c"]),
+                ("T", &["c", "a"]),
+                ("U", &["d"]),
+            ]),
+            create_cyclic_example_query(),
+        ];
+        for query in acyclic.iter().chain(&cyclic) {
+            assert_eq!(
+                acyclic_join_forest(query).is_some(),
+                gyo_reduce(query).is_empty()
+            );
+        }
+        for query in &acyclic {
+            assert!(acyclic_join_forest(query).is_some());
+            let forest = build_join_forest(query, &Statistics::default());
+            assert_eq!(validate_join_forest(query, &forest), Ok(()));
+        }
+        for query in &cyclic {
+            assert!(acyclic_join_forest(query).is_none());
+        }
+    }
+
+    #[test]
+    fn classify_takes_the_strongest_class() {
+        let alpha = query(&[
+            ("R", &["a", "b", "c"]),
+            ("S", &["a", "b"]),
+            ("T", &["b", "c"]),
+            ("U", &["c", "a"]),
+        ]);
+        let beta = query(&[
+            ("R", &["a", "b", "c"]),
+            ("S", &["a", "b"]),
+            ("T", &["b", "c"]),
+        ]);
+        let gamma = query(&[("R", &["a", "b", "c"]), ("S", &["a", "b"])]);
+        let berge = query(&[("R", &["a", "b"]), ("S", &["b", "c"]), ("T", &["c", "d"])]);
+        let cyclic = query(&[("R", &["a", "b"]), ("S", &["b", "c"]), ("T", &["c", "a"])]);
+        assert_eq!(classify(&alpha), Acyclicity::Alpha);
+        assert_eq!(classify(&beta), Acyclicity::Beta);
+        assert_eq!(classify(&gamma), Acyclicity::Gamma);
+        assert_eq!(classify(&berge), Acyclicity::Berge);
+        assert_eq!(classify(&cyclic), Acyclicity::Cyclic);
+    }
 }
@@ -0,0 +1,170 @@
+// Hypergraphs: the vertices are the variables of a query and every body atom is a named
+// hyperedge over its variables (constants are not vertices, they are never shared).
+// The incidence index gives the hyperedges of every vertex, so gyo, the join trees and the
+// decompositions all work on the same structure instead of rebuilding it.
+
+use std::collections::HashMap;
+
+use crate::queries::{Atom, ConjunctiveQuery};
+
+// a named hyperedge, its vertices are indexes into the vertices of the hypergraph.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hyperedge {
+    pub name: &'static str,
+    pub vertices: Vec<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hypergraph {
+    pub vertices: Vec<&'static str>,
+    pub edges: Vec<Hyperedge>,
+    // the edges that contain a vertex, for every vertex.
+    incidence: Vec<Vec<usize>>,
+}
+
+impl Hypergraph {
+    pub fn new(vertices: Vec<&'static str>, edges: Vec<Hyperedge>) -> Hypergraph {
+        let mut incidence = vec![Vec::new(); vertices.len()];
+        for (e, edge) in edges.iter().enumerate() {
+            for &v in &edge.vertices {
+                incidence[v].push(e);
+            }
+        }
+        Hypergraph {
+            vertices,
+            edges,
+            incidence,
+        }
+    }
+
+    // the hypergraph of the body atoms of a query.
+    pub fn from_query(query: &ConjunctiveQuery) -> Hypergraph {
+        Hypergraph::from_atoms(&query.body_atoms)
+    }
+
+    // an edge per atom, the vertices in order of appearance.
+    pub fn from_atoms(atoms: &[Atom]) -> Hypergraph {
+        let mut vertices: Vec<&'static str> = Vec::new();
+        let mut indexes: HashMap<&'static str, usize> = HashMap::new();
+        let mut edges = Vec::new();
+        for atom in atoms {
+            let mut edge = Vec::new();
+            for variable in atom.variables() {
+                let index = *indexes.entry(variable).or_insert_with(|| {
+                    vertices.push(variable);
+                    vertices.len() - 1
+                });
+                edge.push(index);
+            }
+            edges.push(Hyperedge {
+                name: atom.name,
+                vertices: edge,
+            });
+        }
+        Hypergraph::new(vertices, edges)
+    }
+
+    // the index of a vertex by name.
+    pub fn vertex(&self, name: &str) -> Option<usize> {
+        self.vertices.iter().position(|v| *v == name)
+    }
+
+    // the edges that contain a vertex.
+    pub fn edges_of(&self, vertex: usize) -> &[usize] {
+        &self.incidence[vertex]
+    }
+
+    // the vertices of an edge that are in another edge too.
+    pub fn shared_vertices(&self, edge: usize) -> Vec<usize> {
+        self.edges[edge]
+            .vertices
+            .iter()
+            .copied()
+            .filter(|&v| self.incidence[v].iter().any(|&e| e != edge))
+            .collect()
+    }
+
+    // the hypergraph induced by some of the vertices: every edge keeps the vertices it has
+    // of them, the edges left without vertices are dropped. the vertices are renumbered.
+    pub fn induced(&self, vertices: &[usize]) -> Hypergraph {
+        let mut index = vec![None; self.vertices.len()];
+        for (new, &v) in vertices.iter().enumerate() {
+            index[v] = Some(new);
+        }
+        let edges = self
+            .edges
+            .iter()
+            .filter_map(|edge| {
+                let kept: Vec<usize> = edge.vertices.iter().filter_map(|&v| index[v]).collect();
+                (!kept.is_empty()).then_some(Hyperedge {
+                    name: edge.name,
+                    vertices: kept,
+                })
+            })
+            .collect();
+        Hypergraph::new(vertices.iter().map(|&v| self.vertices[v]).collect(), edges)
+    }
+
+    // the primal (gaifman) graph: two vertices are adjacent if they are in the same edge.
+    // the neighbours of every vertex, in order.
+    pub fn primal_graph(&self) -> Vec<Vec<usize>> {
+        (0..self.vertices.len())
+            .map(|v| {
+                let mut neighbours: Vec<usize> = self.incidence[v]
+                    .iter()
+                    .flat_map(|&e| self.edges[e].vertices.iter().copied())
+                    .filter(|&u| u != v)
+                    .collect();
+                neighbours.sort_unstable();
+                neighbours.dedup();
+                neighbours
+            })
+            .collect()
+    }
+
+    // the dual graph: two edges are adjacent if they share a vertex.
+    // the neighbours of every edge, in order.
+    pub fn dual_graph(&self) -> Vec<Vec<usize>> {
+        (0..self.edges.len())
+            .map(|e| {
+                let mut neighbours: Vec<usize> = self.edges[e]
+                    .vertices
+                    .iter()
+                    .flat_map(|&v| self.incidence[v].iter().copied())
+                    .filter(|&f| f != e)
+                    .collect();
+                neighbours.sort_unstable();
+                neighbours.dedup();
+                neighbours
+            })
+            .collect()
+    }
+
+    // the connected components: the edges of every component, in order of their first edge.
+    // an edge without vertices is a component on its own.
+    pub fn components(&self) -> Vec<Vec<usize>> {
+        let dual = self.dual_graph();
+        let mut component_of = vec![usize::MAX; self.edges.len()];
+        let mut components: Vec<Vec<usize>> = Vec::new();
+        for start in 0..self.edges.len() {
+            if component_of[start] != usize::MAX {
+                continue;
+            }
+            let mut component = vec![start];
+            component_of[start] = components.len();
+            let mut next = 0;
+            while next < component.len() {
+                for &f in &dual[component[next]] {
+                    if component_of[f] == usize::MAX {
+                        component_of[f] = components.len();
+                        component.push(f);
+                    }
+                }
+                next += 1;
+            }
+            component.sort_unstable();
+            components.push(component);
+        }
+        components
+    }
+}
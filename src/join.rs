@@ -0,0 +1,290 @@
+// Final join phase of yannakakis:
+// the (reduced) relations are joined on the variables they share.
+// Every relation here has one column per variable, named after the variable.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::Arc;
+
+use arrow::{
+    array::{Array, ArrayRef, Float64Array, Int64Array, StringArray, UInt32Array},
+    compute::take,
+    datatypes::{DataType, Field, Schema},
+    record_batch::{RecordBatch, RecordBatchOptions},
+};
+
+// A single value of a column, used as (part of) a hash key.
+// Floats are stored as their bit pattern so the value can be hashed.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Value {
+    Null,
+    Int64(i64),
+    Float64(u64),
+    Utf8(String),
+}
+
+// the value as it is written in a csv file, null is empty.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Null => Ok(()),
+            Value::Int64(value) => write!(f, "{}", value),
+            Value::Float64(bits) => write!(f, "{}", f64::from_bits(*bits)),
+            Value::Utf8(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+// get the value at row of a column.
+pub fn value_at(column: &ArrayRef, row: usize) -> Value {
+    if column.is_null(row) {
+        return Value::Null;
+    }
+    match column.data_type() {
+        DataType::Utf8 => {
+            let values = column.as_any().downcast_ref::<StringArray>().unwrap();
+            Value::Utf8(values.value(row).to_string())
+        }
+        DataType::Int64 => {
+            let values = column.as_any().downcast_ref::<Int64Array>().unwrap();
+            Value::Int64(values.value(row))
+        }
+        DataType::Float64 => {
+            let values = column.as_any().downcast_ref::<Float64Array>().unwrap();
+            Value::Float64(values.value(row).to_bits())
+        }
+        // a column that was not loaded (see loader).
+        DataType::Null => Value::Null,
+        data_type => panic!("Unsupported data type: {:?}", data_type),
+    }
+}
+
+// make an arrow column of the given type from values.
+pub fn values_to_array(values: &[Value], data_type: &DataType) -> ArrayRef {
+    match data_type {
+        DataType::Utf8 => Arc::new(
+            values
+                .iter()
+                .map(|value| match value {
+                    Value::Utf8(value) => Some(value.as_str()),
+                    _ => None,
+                })
+                .collect::<StringArray>(),
+        ),
+        DataType::Int64 => Arc::new(
+            values
+                .iter()
+                .map(|value| match value {
+                    Value::Int64(value) => Some(*value),
+                    _ => None,
+                })
+                .collect::<Int64Array>(),
+        ),
+        DataType::Float64 => Arc::new(
+            values
+                .iter()
+                .map(|value| match value {
+                    Value::Float64(bits) => Some(f64::from_bits(*bits)),
+                    _ => None,
+                })
+                .collect::<Float64Array>(),
+        ),
+        data_type => panic!("Unsupported data type: {:?}", data_type),
+    }
+}
+
+// How NULL values compare in semijoins, selections and the final join.
+// Sql follows SQL three-valued logic: NULL = x is unknown, so a NULL never
+// joins and never passes a selection, not even against another NULL.
+// NullsEqual treats two NULLs as equal (like IS NOT DISTINCT FROM); a NULL
+// still never equals a constant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NullPolicy {
+    #[default]
+    Sql,
+    NullsEqual,
+}
+
+impl NullPolicy {
+    // compare two (possibly NULL) values under this policy.
+    pub fn matches<T: PartialEq>(&self, value1: Option<T>, value2: Option<T>) -> bool {
+        match (value1, value2) {
+            (Some(value1), Some(value2)) => value1 == value2,
+            (None, None) => *self == NullPolicy::NullsEqual,
+            _ => false,
+        }
+    }
+}
+
+// make the join key of a row, None if the row can not join under the null policy.
+pub fn row_key(columns: &[ArrayRef], row: usize, null_policy: NullPolicy) -> Option<Vec<Value>> {
+    let key: Vec<Value> = columns.iter().map(|column| value_at(column, row)).collect();
+    if null_policy == NullPolicy::Sql && key.contains(&Value::Null) {
+        None
+    } else {
+        Some(key)
+    }
+}
+
+// get the columns of a relation by (variable) name.
+fn columns_of(relation: &RecordBatch, names: &[String]) -> Vec<ArrayRef> {
+    names
+        .iter()
+        .map(|name| relation.column(relation.schema().index_of(name).unwrap()).clone())
+        .collect()
+}
+
+// natural join of two relations on their common column names.
+// the result has all columns of relation1 followed by the other columns of relation2.
+pub fn hash_join(
+    relation1: &RecordBatch,
+    relation2: &RecordBatch,
+    null_policy: NullPolicy,
+) -> RecordBatch {
+    let schema1 = relation1.schema();
+    let schema2 = relation2.schema();
+    // the variables both relations share.
+    let common: Vec<String> = schema1
+        .fields()
+        .iter()
+        .map(|field| field.name().clone())
+        .filter(|name| schema2.index_of(name).is_ok())
+        .collect();
+
+    // build a hash table on relation2.
+    let key_columns2 = columns_of(relation2, &common);
+    let mut table: HashMap<Vec<Value>, Vec<u32>> = HashMap::new();
+    for row in 0..relation2.num_rows() {
+        if let Some(key) = row_key(&key_columns2, row, null_policy) {
+            table.entry(key).or_default().push(row as u32);
+        }
+    }
+
+    // probe with relation1.
+    let key_columns1 = columns_of(relation1, &common);
+    let mut indices1: Vec<u32> = Vec::new();
+    let mut indices2: Vec<u32> = Vec::new();
+    for row in 0..relation1.num_rows() {
+        if let Some(matches) = row_key(&key_columns1, row, null_policy)
+            .and_then(|key| table.get(&key))
+        {
+            for &match_row in matches {
+                indices1.push(row as u32);
+                indices2.push(match_row);
+            }
+        }
+    }
+    let indices1 = UInt32Array::from(indices1);
+    let indices2 = UInt32Array::from(indices2);
+
+    // gather the columns of the result.
+    let mut fields: Vec<Field> = Vec::new();
+    let mut columns: Vec<ArrayRef> = Vec::new();
+    for (field, column) in schema1.fields().iter().zip(relation1.columns()) {
+        fields.push(field.as_ref().clone());
+        columns.push(take(column, &indices1, None).unwrap());
+    }
+    for (field, column) in schema2.fields().iter().zip(relation2.columns()) {
+        if !common.contains(field.name()) {
+            fields.push(field.as_ref().clone());
+            columns.push(take(column, &indices2, None).unwrap());
+        }
+    }
+    make_record_batch(fields, columns, indices1.len())
+}
+
+// keep only the given columns of a relation (in the given order).
+pub fn project(relation: &RecordBatch, names: &[String]) -> RecordBatch {
+    let schema = relation.schema();
+    let fields = names
+        .iter()
+        .map(|name| schema.field_with_name(name).unwrap().clone())
+        .collect();
+    make_record_batch(fields, columns_of(relation, names), relation.num_rows())
+}
+
+// make a record batch, also when there are no columns (for boolean queries).
+pub fn make_record_batch(fields: Vec<Field>, columns: Vec<ArrayRef>, num_rows: usize) -> RecordBatch {
+    let options = RecordBatchOptions::new().with_row_count(Some(num_rows));
+    RecordBatch::try_new_with_options(Arc::new(Schema::new(fields)), columns, &options).unwrap()
+}
+
+// remove duplicate rows (set semantics), two NULLs count as the same value here.
+pub fn distinct(relation: &RecordBatch) -> RecordBatch {
+    let columns = relation.columns().to_vec();
+    let mut seen = HashSet::new();
+    let mut indices: Vec<u32> = Vec::new();
+    for row in 0..relation.num_rows() {
+        let key = row_key(&columns, row, NullPolicy::NullsEqual).unwrap();
+        if seen.insert(key) {
+            indices.push(row as u32);
+        }
+    }
+    select_rows(relation, &indices)
+}
+
+// copy the given rows of a relation (in the given order) into a new relation.
+pub fn select_rows(relation: &RecordBatch, rows: &[u32]) -> RecordBatch {
+    let indices = UInt32Array::from(rows.to_vec());
+    let columns = relation
+        .columns()
+        .iter()
+        .map(|column| take(column, &indices, None).unwrap())
+        .collect();
+    let fields = relation
+        .schema()
+        .fields()
+        .iter()
+        .map(|field| field.as_ref().clone())
+        .collect();
+    make_record_batch(fields, columns, indices.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a relation of Int64 columns from its rows, None is NULL.
+    fn relation(columns: &[&str], rows: &[Vec<Option<i64>>]) -> RecordBatch {
+        let fields = columns
+            .iter()
+            .map(|&column| Field::new(column, DataType::Int64, true))
+            .collect();
+        let arrays = (0..columns.len())
+            .map(|c| Arc::new(rows.iter().map(|row| row[c]).collect::<Int64Array>()) as ArrayRef)
+            .collect();
+        make_record_batch(fields, arrays, rows.len())
+    }
+
+    #[test]
+    fn null_policy_compares_nulls() {
+        assert!(NullPolicy::Sql.matches(Some(1), Some(1)));
+        assert!(!NullPolicy::Sql.matches::<i64>(None, None));
+        assert!(NullPolicy::NullsEqual.matches::<i64>(None, None));
+        assert!(!NullPolicy::NullsEqual.matches(None, Some(1)));
+    }
+
+    #[test]
+    fn hash_join_on_null_keys() {
+        let left = relation(
+            &["a", "b"],
+            &[
+                vec![Some(1), Some(10)],
+                vec![None, Some(11)],
+                vec![Some(2), Some(12)],
+            ],
+        );
+        let right = relation(
+            &["a", "c"],
+            &[
+                vec![Some(1), Some(20)],
+                vec![None, Some(21)],
+                vec![None, Some(22)],
+            ],
+        );
+        assert_eq!(hash_join(&left, &right, NullPolicy::Sql).num_rows(), 1);
+        // the NULL of the left joins both NULLs of the right.
+        let joined = hash_join(&left, &right, NullPolicy::NullsEqual);
+        assert_eq!(joined.num_rows(), 3);
+    }
+}
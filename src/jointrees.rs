@@ -1,219 +1,812 @@
 // Join trees:
 
 use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::time::Instant;
 
 use arrow::{
-    array::{Array, BooleanArray, Float64Array, Int64Array, StringArray},
-    compute::filter_record_batch,
+    array::{Array, ArrayRef, BooleanArray, Float64Array, Int64Array, StringArray},
     datatypes::DataType,
     record_batch::RecordBatch,
 };
+use serde::Serialize;
+
+use crate::bloom::{BloomFilter, BloomFilterPass};
+use crate::coercion::{cast_key, common_type, KeyCoercion};
+use crate::cost::{join_tree_cost, Statistics};
+use crate::explain::{elapsed_us, BloomFilterReport, SemiJoinReport};
+use crate::gyo::join_forest;
+use crate::hypergraph::Hypergraph;
+use crate::join::{row_key, NullPolicy, Value};
 
 // Given a hypergraph H = (V,E), a tree T is a join tree of H if
 // • the nodes of T are precisely the hyperedges in E and,
 // • for each node v in V , the set of nodes of T in which v is an element
 // forms a connected subtree of T.
-use crate::queries::{Atom, Term};
+use crate::queries::{Atom, ConjunctiveQuery};
 
 // struct for a joinTree(node)
 #[derive(Debug, Clone, PartialEq)]
 pub struct JoinTreeNode {
     relation: String,
-    common_term: Vec<&'static Term>,
+    common_variables: Vec<&'static str>,
     children: Vec<JoinTreeNode>,
 }
 
 impl JoinTreeNode {
-    fn new(relation: String, common_term: Vec<&'static Term>) -> JoinTreeNode {
+    fn new(relation: String, common_variables: Vec<&'static str>) -> JoinTreeNode {
         JoinTreeNode {
             relation,
-            common_term,
+            common_variables,
             children: Vec::new(),
         }
     }
     fn add_child(&mut self, child: JoinTreeNode) {
         self.children.push(child);
     }
-}
 
-// build the join tree from the nodes
-fn build_tree(nodes: Vec<JoinTreeNode>) -> Option<JoinTreeNode> {
-    let mut thisnodes = nodes.clone();
+    // the label of a node: the relation and the variables it shares with other atoms.
+    fn label(&self) -> String {
+        format!("{} [{}]", self.relation, self.common_variables.join(", "))
+    }
 
-    if let Some(thisnode) = thisnodes.pop() {
-        let mut lnode = thisnode.clone();
+    // the variables a node shares with its child.
+    fn shared_with(&self, child: &JoinTreeNode) -> Vec<String> {
+        self.common_variables
+            .iter()
+            .filter(|variable| child.common_variables.contains(variable))
+            .map(|variable| variable.to_string())
+            .collect()
+    }
 
-        let mut remaining_nodes = Vec::new();
-        let mut nodes_to_remove = Vec::new();
-        
-        for node in &thisnodes {
-            let mut common_found = false;
+    // the join tree in graphviz dot format,
+    // the edges are labelled with the terms a parent shares with its child.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("graph join_tree {\n    node [shape=box];\n");
+        let mut next_id = 0;
+        self.write_dot(&mut dot, &mut next_id);
+        dot.push_str("}\n");
+        dot
+    }
 
-            // Check all common terms with the current thisnode
-            for common in &thisnode.common_term {
-                if node.common_term.contains(common) {
-                    common_found = true;
-                    break;
-                }
-            }
+    fn write_dot(&self, dot: &mut String, next_id: &mut usize) -> usize {
+        let id = *next_id;
+        *next_id += 1;
+        dot.push_str(&format!("    n{} [label=\"{}\"];\n", id, self.label()));
+        for child in &self.children {
+            let child_id = child.write_dot(dot, next_id);
+            dot.push_str(&format!(
+                "    n{} -- n{} [label=\"{}\"];\n",
+                id,
+                child_id,
+                self.shared_with(child).join(", ")
+            ));
+        }
+        id
+    }
+
+    // the join tree as an indented ascii tree for terminals.
+    pub fn to_ascii(&self) -> String {
+        let mut ascii = format!("{}\n", self.label());
+        self.write_ascii(&mut ascii, "");
+        ascii
+    }
 
-            if common_found {
-                lnode.add_child(node.clone());
-                nodes_to_remove.push(node.clone());
+    fn write_ascii(&self, ascii: &mut String, prefix: &str) {
+        for (index, child) in self.children.iter().enumerate() {
+            let last = index + 1 == self.children.len();
+            let (branch, indent) = if last {
+                ("`-- ", "    ")
             } else {
-                remaining_nodes.push(node.clone());
-            }
+                ("|-- ", "|   ")
+            };
+            ascii.push_str(&format!("{}{}{}\n", prefix, branch, child.label()));
+            child.write_ascii(ascii, &format!("{}{}", prefix, indent));
         }
+    }
+}
 
-        // verify each child with remaining nodes.
+// the direction of a semijoin step in the join tree.
+// BottomUp filters a parent with a child (forward pass),
+// TopDown filters a child with its parent (backward pass).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Direction {
+    BottomUp,
+    TopDown,
+}
 
-        for child in &mut lnode.children {
-            // remove the nodes that are childs now.
-            thisnodes.retain(|n| !nodes_to_remove.contains(n));
+// a variable the semijoin is done on, with its column in the target and in the source.
+// the columns are those of the relations bound to their atom (see Atom::variables).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct KeyColumn {
+    pub variable: String,
+    pub target_column: usize,
+    pub source_column: usize,
+}
 
-            let mut child_clone = child.clone();
+// one semijoin: target := target ⋉ source on the key variables.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SemiJoinStep {
+    pub target: String,
+    pub source: String,
+    pub keys: Vec<KeyColumn>,
+    pub direction: Direction,
+}
 
-            for node in &thisnodes {
-                let mut common_found = false;
+// the semijoins of the full reducer: first the bottom-up steps (children before their parent),
+// then the top-down steps (parents before their children).
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize)]
+pub struct SemiJoinProgram {
+    // the root of every join tree, also of a tree with a single relation and no steps.
+    pub roots: Vec<String>,
+    pub steps: Vec<SemiJoinStep>,
+}
 
-                // Check all common terms with the child
-                for common in &child_clone.common_term {
-                    if node.common_term.contains(common) {
-                        common_found = true;
-                        break;
-                    }
-                }
-                if common_found {
-                    // Add the node from thisnodes as a child of the current child
-                    child_clone.add_child(node.clone());
-                }
-            }
-            *child = child_clone;
+impl SemiJoinProgram {
+    // the full reducer of a forest from its roots and its bottom-up steps (children before
+    // their parent): the top-down pass does the same semijoins the other way around, in
+    // reverse order.
+    pub fn new(roots: Vec<String>, bottom_up: Vec<SemiJoinStep>) -> SemiJoinProgram {
+        let top_down: Vec<SemiJoinStep> = bottom_up
+            .iter()
+            .rev()
+            .map(|step| SemiJoinStep {
+                target: step.source.clone(),
+                source: step.target.clone(),
+                keys: step
+                    .keys
+                    .iter()
+                    .map(|key| KeyColumn {
+                        variable: key.variable.clone(),
+                        target_column: key.source_column,
+                        source_column: key.target_column,
+                    })
+                    .collect(),
+                direction: Direction::TopDown,
+            })
+            .collect();
+        let mut steps = bottom_up;
+        steps.extend(top_down);
+        SemiJoinProgram { roots, steps }
+    }
+
+    // the steps of one pass, in execution order.
+    pub fn pass(&self, direction: Direction) -> Vec<SemiJoinStep> {
+        self.steps
+            .iter()
+            .filter(|step| step.direction == direction)
+            .cloned()
+            .collect()
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+}
+
+impl fmt::Display for SemiJoinStep {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let direction = match self.direction {
+            Direction::BottomUp => "bottom-up",
+            Direction::TopDown => "top-down",
+        };
+        let keys: Vec<String> = self
+            .keys
+            .iter()
+            .map(|key| {
+                format!(
+                    "{} ({}.{} = {}.{})",
+                    key.variable, self.target, key.target_column, self.source, key.source_column
+                )
+            })
+            .collect();
+        write!(
+            f,
+            "{:<9} {} ⋉ {} on {}",
+            direction,
+            self.target,
+            self.source,
+            keys.join(", ")
+        )
+    }
+}
+
+impl fmt::Display for SemiJoinProgram {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for step in &self.steps {
+            writeln!(f, "{}", step)?;
         }
+        Ok(())
+    }
+}
 
-        Some(lnode)
-    } else {
-        None
+// with at most this many join forests the cheapest of all of them is taken.
+const ENUMERATED_JOIN_FORESTS: usize = 100;
+
+// build the join forest of a query: a join tree for every connected component
+// (atoms that share no variables, directly or through other atoms, are in different trees).
+// the cheapest forest for the statistics is taken, the first one on a tie, so the same query
+// always gets the same trees. when there are too many forests to try them all, one maximum
+// spanning forest is taken and only the roots of its trees are chosen by cost.
+// there is no join forest when the query is cyclic.
+pub fn build_join_forest(query: &ConjunctiveQuery, statistics: &Statistics) -> Vec<JoinTreeNode> {
+    let hypergraph = Hypergraph::from_query(query);
+    if join_forest(&hypergraph).is_none() {
+        return Vec::new();
     }
+    let head = query.head_atom.variables();
+    let forests = maximum_spanning_forests(&hypergraph, ENUMERATED_JOIN_FORESTS + 1);
+    let (parents, roots, _) = if forests.len() <= ENUMERATED_JOIN_FORESTS {
+        forests
+            .iter()
+            .map(|links| root_forest(&hypergraph, links, &head, statistics))
+            .min_by(|(_, _, cost1), (_, _, cost2)| cost1.total_cmp(cost2))
+            .unwrap()
+    } else {
+        let (links, _) = maximum_spanning_forest(&hypergraph, &dual_links(&hypergraph));
+        root_forest(&hypergraph, &links, &head, statistics)
+    };
+    forest_nodes(&hypergraph, &parents, &roots)
 }
 
-pub fn join_tree(atoms: &Vec<Atom>) -> Vec<Vec<String>> {
-    let mut term_set: HashMap<&'static str, HashSet<&Term>> = HashMap::new();
-    for atom in atoms {
-        term_set.insert(atom.name, atom.terms.clone().into_iter().collect());
+// every join forest of a query with its estimated cost, the cheapest first (in order on a tie).
+// the join forests are the maximum weight spanning forests of the dual graph, weighted by the
+// number of shared variables, and every tree is rooted at its cheapest atom.
+// there can be very many: n atoms that share one variable have n^(n-2) join trees.
+// there is no join forest when the query is cyclic.
+pub fn all_join_forests(
+    query: &ConjunctiveQuery,
+    statistics: &Statistics,
+) -> Vec<(Vec<JoinTreeNode>, f64)> {
+    let hypergraph = Hypergraph::from_query(query);
+    if join_forest(&hypergraph).is_none() {
+        return Vec::new();
     }
-    // Remove items that are unique to each set
-    for set_name in term_set.keys().cloned().collect::<Vec<_>>() {
-        let intersection: HashSet<_> = term_set
-            .values()
-            .filter(|&other_set| other_set != term_set.get(&set_name).unwrap())
-            .flat_map(|other_set| other_set.iter())
-            .cloned()
+    let head = query.head_atom.variables();
+    let mut forests: Vec<(Vec<JoinTreeNode>, f64)> =
+        maximum_spanning_forests(&hypergraph, usize::MAX)
+            .iter()
+            .map(|links| {
+                let (parents, roots, cost) = root_forest(&hypergraph, links, &head, statistics);
+                (forest_nodes(&hypergraph, &parents, &roots), cost)
+            })
             .collect();
+    forests.sort_by(|(_, cost1), (_, cost2)| cost1.total_cmp(cost2));
+    forests
+}
 
-        let set = term_set.get_mut(&set_name).unwrap();
-        set.retain(|item| intersection.contains(item));
+// the links of the dual graph of a hypergraph with their weight (the number of shared
+// vertices), the heaviest first and in order on a tie.
+fn dual_links(hypergraph: &Hypergraph) -> Vec<(usize, usize, usize)> {
+    let mut links: Vec<(usize, usize, usize)> = Vec::new();
+    for (e, neighbours) in hypergraph.dual_graph().iter().enumerate() {
+        for &f in neighbours.iter().filter(|&&f| f > e) {
+            let shared = hypergraph.edges[e]
+                .vertices
+                .iter()
+                .filter(|v| hypergraph.edges[f].vertices.contains(v))
+                .count();
+            links.push((shared, e, f));
+        }
     }
+    links.sort_by(|(w1, e1, f1), (w2, e2, f2)| w2.cmp(w1).then(e1.cmp(e2)).then(f1.cmp(f2)));
+    links
+}
 
-    // Build the join tree
-    let mut join_tree_nodes: Vec<JoinTreeNode> = Vec::new();
+// a maximum weight spanning forest of the dual graph (kruskal) and its weight.
+fn maximum_spanning_forest(
+    hypergraph: &Hypergraph,
+    links: &[(usize, usize, usize)],
+) -> (Vec<(usize, usize)>, usize) {
+    let mut trees: Vec<usize> = (0..hypergraph.edges.len()).collect();
+    let mut members: Vec<Vec<usize>> = (0..hypergraph.edges.len()).map(|e| vec![e]).collect();
+    let mut forest = Vec::new();
+    let mut weight = 0;
+    for &(link_weight, e, f) in links {
+        let (to, from) = (trees[e], trees[f]);
+        if to != from {
+            // relabel the smaller tree.
+            let (to, from) = match members[to].len() < members[from].len() {
+                true => (from, to),
+                false => (to, from),
+            };
+            for member in std::mem::take(&mut members[from]) {
+                trees[member] = to;
+                members[to].push(member);
+            }
+            forest.push((e, f));
+            weight += link_weight;
+        }
+    }
+    (forest, weight)
+}
+
+// the maximum weight spanning forests of the dual graph of a hypergraph, at most limit of them.
+// a forest is a list of links between edges.
+fn maximum_spanning_forests(hypergraph: &Hypergraph, limit: usize) -> Vec<Vec<(usize, usize)>> {
+    let edges = hypergraph.edges.len();
+    let links = dual_links(hypergraph);
+    let (_, maximum) = maximum_spanning_forest(hypergraph, &links);
+    let mut forests = Vec::new();
+    spanning_forests(
+        &links,
+        &mut (0..edges).collect(),
+        &mut Vec::new(),
+        edges - hypergraph.components().len(),
+        maximum,
+        limit,
+        &mut forests,
+    );
+    forests
+}
 
-    for (index, (current_relation, current_terms)) in term_set.iter().enumerate() {
-        let mut current_node = JoinTreeNode::new(
-            current_relation.to_string(),
-            current_terms.iter().cloned().collect(),
+// the spanning forests with the given weight: every link is taken (when it connects two trees)
+// or left out, a branch stops when the heaviest links left can not make up the weight.
+fn spanning_forests(
+    links: &[(usize, usize, usize)],
+    trees: &mut Vec<usize>,
+    chosen: &mut Vec<(usize, usize)>,
+    size: usize,
+    weight: usize,
+    limit: usize,
+    forests: &mut Vec<Vec<(usize, usize)>>,
+) {
+    if forests.len() == limit {
+        return;
+    }
+    if chosen.len() == size {
+        forests.push(chosen.clone());
+        return;
+    }
+    let left = size - chosen.len();
+    if links.len() < left || links[..left].iter().map(|link| link.0).sum::<usize>() < weight {
+        return;
+    }
+    let (link_weight, e, f) = links[0];
+    if trees[e] != trees[f] {
+        let mut joined = trees.clone();
+        let (from, to) = (trees[f], trees[e]);
+        joined
+            .iter_mut()
+            .filter(|t| **t == from)
+            .for_each(|t| *t = to);
+        chosen.push((e, f));
+        let weight = weight - link_weight;
+        spanning_forests(
+            &links[1..],
+            &mut joined,
+            chosen,
+            size,
+            weight,
+            limit,
+            forests,
         );
-        join_tree_nodes.insert(index, current_node);
-    }
-    // build the tree from the nodes
-    let join_tree = build_tree(join_tree_nodes.clone());
-    // extract information from the join_three for the semijoin
-    let mut semi_join_info = get_semi_join_info(&join_tree.unwrap(), None);
-    // remove last element (not needed)
-    semi_join_info.pop();
-    // return the semi_join_info
-    semi_join_info
-}
-
-// Go trough the JoinTreeNode and extract information for the semijoin.
-fn get_semi_join_info(node: &JoinTreeNode, parent: Option<&JoinTreeNode>) -> Vec<Vec<String>> {
-    // the vectors to return to semijoin.
-    let mut result = Vec::new();
+        chosen.pop();
+    }
+    spanning_forests(&links[1..], trees, chosen, size, weight, limit, forests);
+}
+
+// root every tree of a forest (a list of links) at its cheapest atom:
+// the parent of every edge, the roots and the estimated cost.
+fn root_forest(
+    hypergraph: &Hypergraph,
+    links: &[(usize, usize)],
+    head: &[&str],
+    statistics: &Statistics,
+) -> (Vec<Option<usize>>, Vec<usize>, f64) {
+    let edges = hypergraph.edges.len();
+    let mut neighbours = vec![Vec::new(); edges];
+    for &(e, f) in links {
+        neighbours[e].push(f);
+        neighbours[f].push(e);
+    }
+    let mut parents = vec![None; edges];
+    let mut roots = Vec::new();
+    let mut cost = 0.0;
+    for component in hypergraph.components() {
+        let (tree_cost, root, tree) = component
+            .iter()
+            .map(|&root| {
+                let tree = rooted_tree(&neighbours, root);
+                let cost = join_tree_cost(hypergraph, &tree, root, head, statistics);
+                (cost, root, tree)
+            })
+            .min_by(|(cost1, _, _), (cost2, _, _)| cost1.total_cmp(cost2))
+            .unwrap();
+        for &e in &component {
+            parents[e] = tree[e];
+        }
+        roots.push(root);
+        cost += tree_cost;
+    }
+    (parents, roots, cost)
+}
+
+// the parents of the tree around a root, from the neighbours of every edge in the forest.
+fn rooted_tree(neighbours: &[Vec<usize>], root: usize) -> Vec<Option<usize>> {
+    let mut parents = vec![None; neighbours.len()];
+    let mut visited = vec![false; neighbours.len()];
+    visited[root] = true;
+    let mut queue = vec![root];
+    let mut next = 0;
+    while next < queue.len() {
+        let e = queue[next];
+        for &other in &neighbours[e] {
+            if !visited[other] {
+                visited[other] = true;
+                parents[other] = Some(e);
+                queue.push(other);
+            }
+        }
+        next += 1;
+    }
+    parents
+}
+
+// the join trees of a forest from the parent of every edge, a node per atom with the variables
+// it shares with other atoms.
+fn forest_nodes(
+    hypergraph: &Hypergraph,
+    parents: &[Option<usize>],
+    roots: &[usize],
+) -> Vec<JoinTreeNode> {
+    let nodes: Vec<JoinTreeNode> = (0..hypergraph.edges.len())
+        .map(|e| {
+            let common_variables = hypergraph
+                .shared_vertices(e)
+                .iter()
+                .map(|&v| hypergraph.vertices[v])
+                .collect();
+            JoinTreeNode::new(hypergraph.edges[e].name.to_string(), common_variables)
+        })
+        .collect();
+    roots
+        .iter()
+        .map(|&root| build_tree(root, &nodes, parents))
+        .collect()
+}
+
+// build the join tree below a node from the parents of the join forest.
+fn build_tree(node: usize, nodes: &[JoinTreeNode], parents: &[Option<usize>]) -> JoinTreeNode {
+    let mut tree = nodes[node].clone();
+    for child in (0..nodes.len()).filter(|&child| parents[child] == Some(node)) {
+        tree.add_child(build_tree(child, nodes, parents));
+    }
+    tree
+}
+
+// the semijoin program of the full reducer on the join forest of a query.
+pub fn join_tree(query: &ConjunctiveQuery, statistics: &Statistics) -> SemiJoinProgram {
+    semi_join_program(query, &build_join_forest(query, statistics))
+}
+
+// the semijoin program of the full reducer on a join forest of a query.
+// the trees share no variables, so every tree reduces its own component.
+pub fn semi_join_program(query: &ConjunctiveQuery, forest: &[JoinTreeNode]) -> SemiJoinProgram {
+    let atoms = &query.body_atoms;
+    // extract the bottom-up semijoins from the join trees
+    let mut roots = Vec::new();
+    let mut steps = Vec::new();
+    for join_tree in forest {
+        get_semi_join_info(join_tree, None, atoms, &mut steps);
+        roots.push(join_tree.relation.clone());
+    }
+    SemiJoinProgram::new(roots, steps)
+}
+
+// why a forest is not a join forest of a query.
+#[derive(Debug, Clone, PartialEq)]
+pub enum JoinTreeError {
+    // the nodes that contain a variable do not form a connected subtree.
+    RunningIntersection {
+        variable: String,
+        nodes: Vec<String>,
+    },
+    // an atom that is not exactly one node of the forest.
+    Atom {
+        relation: String,
+        nodes: usize,
+    },
+}
+
+impl fmt::Display for JoinTreeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            JoinTreeError::RunningIntersection { variable, nodes } => write!(
+                f,
+                "the nodes with {} are not connected: {}",
+                variable,
+                nodes.join(", ")
+            ),
+            JoinTreeError::Atom { relation, nodes } => {
+                write!(
+                    f,
+                    "atom {} is in {} nodes of the join forest",
+                    relation, nodes
+                )
+            }
+        }
+    }
+}
+
+impl Error for JoinTreeError {}
+
+// check that a forest is a join forest of a query: every atom is one node and for every
+// variable the nodes that contain it form a connected subtree of one of the trees
+// (the running intersection property).
+pub fn validate_join_forest(
+    query: &ConjunctiveQuery,
+    forest: &[JoinTreeNode],
+) -> Result<(), JoinTreeError> {
+    // every node with its parent.
+    fn collect<'t>(
+        node: &'t JoinTreeNode,
+        parent: Option<&'t str>,
+        nodes: &mut Vec<(&'t str, Option<&'t str>)>,
+    ) {
+        nodes.push((&node.relation, parent));
+        for child in &node.children {
+            collect(child, Some(&node.relation), nodes);
+        }
+    }
+    let mut nodes = Vec::new();
+    for tree in forest {
+        collect(tree, None, &mut nodes);
+    }
+    for atom in &query.body_atoms {
+        let count = nodes.iter().filter(|(node, _)| *node == atom.name).count();
+        if count != 1 {
+            return Err(JoinTreeError::Atom {
+                relation: atom.name.to_string(),
+                nodes: count,
+            });
+        }
+    }
+    let contains =
+        |node: &str, variable: &str| atom_variables(&query.body_atoms, node).contains(&variable);
+    for variable in Hypergraph::from_query(query).vertices {
+        // a connected subtree has one top: the only node with the variable whose parent
+        // does not have it.
+        let tops = nodes
+            .iter()
+            .filter(|(node, parent)| {
+                contains(node, variable) && !parent.is_some_and(|parent| contains(parent, variable))
+            })
+            .count();
+        if tops > 1 {
+            return Err(JoinTreeError::RunningIntersection {
+                variable: variable.to_string(),
+                nodes: nodes
+                    .iter()
+                    .filter(|(node, _)| contains(node, variable))
+                    .map(|(node, _)| node.to_string())
+                    .collect(),
+            });
+        }
+    }
+    Ok(())
+}
+
+// Go trough the JoinTreeNode and make the bottom-up semijoin steps (children first).
+fn get_semi_join_info(
+    node: &JoinTreeNode,
+    parent: Option<&JoinTreeNode>,
+    atoms: &[Atom],
+    steps: &mut Vec<SemiJoinStep>,
+) {
     // dive in the three.
     for child in &node.children {
-        result.extend_from_slice(&get_semi_join_info(child, Some(node)));
-    }
-    // Procces the nodes.
-    let mut current_node = node.clone();
-    let mut current_parent = parent.unwrap_or(node).clone();
-    current_node.children.clear();
-    current_parent.children.clear();
-    // Find the common common_term.
-    let p_set: HashSet<_> = current_parent.common_term.into_iter().collect();
-    let n_set: HashSet<_> = current_node.common_term.into_iter().collect();
-    let common_term: HashSet<_> = p_set.intersection(&n_set).cloned().collect();
-    // get the string of the common term.
-    let common = if let Some(Term::Variable(value)) = common_term.iter().next() {
-        value
-    } else {
-        "default_value"
+        get_semi_join_info(child, Some(node), atoms, steps);
+    }
+    // the root has no parent to reduce.
+    let Some(parent) = parent else {
+        return;
     };
-    // make the vector for semijoin: relation1, relation2, common_term.
-    let semi_join_info = vec![
-        current_parent.relation,
-        current_node.relation,
-        common.to_string(),
-    ];
-    result.push(semi_join_info);
-    result
-}
-
-// make boolean array to filter realtion1 in semijoin.
-fn make_boolean_array(
-    relation1: &RecordBatch,
-    column_index_r1: usize,
-    relation2: &RecordBatch,
-    column_index_r2: usize,
-) -> BooleanArray {
-    // Extract the columns from the RecordBatches
-    let col_r1 = relation1.column(column_index_r1);
-    let col_r2 = relation2.column(column_index_r2);
-    // request the datatype of the column(s)
-    let data_type = col_r1.data_type();
-
-    // Create a boolean array to represent the result of the semi-join
-    let mut result = vec![false; relation1.num_rows()];
-
-    {
-        match col_r1.data_type() {
-            &DataType::Utf8 => {
-                let values_r1 = col_r1.as_any().downcast_ref::<StringArray>().unwrap();
-                let values_r2 = col_r2.as_any().downcast_ref::<StringArray>().unwrap();
-                for (i, value_r1) in values_r1.iter().enumerate() {
-                    result[i] = values_r2.iter().any(|value_r2| value_r1 == value_r2);
-                }
-            }
-            &DataType::Int64 => {
-                let values_r1 = col_r1.as_any().downcast_ref::<Int64Array>().unwrap();
-                let values_r2 = col_r2.as_any().downcast_ref::<Int64Array>().unwrap();
-                for (i, value_r1) in values_r1.iter().enumerate() {
-                    result[i] = values_r2.iter().any(|value_r2| value_r1 == value_r2);
-                }
-            }
-            &DataType::Float64 => {
-                let values_r1 = col_r1.as_any().downcast_ref::<Float64Array>().unwrap();
-                let values_r2 = col_r2.as_any().downcast_ref::<Float64Array>().unwrap();
-                for (i, value_r1) in values_r1.iter().enumerate() {
-                    result[i] = values_r2.iter().any(|value_r2| value_r1 == value_r2);
-                }
-            }
-            _ => panic!("Unsupported data type: {:?}", col_r1.data_type()),
+    // Find the common variables: the variables the semijoin is done on.
+    let p_set: HashSet<&str> = parent.common_variables.iter().copied().collect();
+    let n_set: HashSet<&str> = node.common_variables.iter().copied().collect();
+    let parent_variables = atom_variables(atoms, &parent.relation);
+    let node_variables = atom_variables(atoms, &node.relation);
+    // resolve the columns of the key variables, in the order of the parent.
+    let keys = parent_variables
+        .iter()
+        .enumerate()
+        .filter(|(_, variable)| p_set.contains(*variable) && n_set.contains(*variable))
+        .filter_map(|(target_column, variable)| {
+            let source_column = node_variables.iter().position(|v| v == variable)?;
+            Some(KeyColumn {
+                variable: variable.to_string(),
+                target_column,
+                source_column,
+            })
+        })
+        .collect();
+    steps.push(SemiJoinStep {
+        target: parent.relation.clone(),
+        source: node.relation.clone(),
+        keys,
+        direction: Direction::BottomUp,
+    });
+}
+
+// the variables of the atom of a relation.
+fn atom_variables(atoms: &[Atom], relation: &str) -> Vec<&'static str> {
+    atoms
+        .iter()
+        .find(|atom| atom.name == relation)
+        .map(|atom| atom.variables())
+        .unwrap_or_default()
+}
+
+// with at least this many selected rows the keys of a semijoin are hashed and probed in parallel.
+const PARALLEL_ROWS: usize = 100_000;
+
+// split the rows of a relation in consecutive ranges, one per thread.
+fn row_ranges(rows: usize, threads: usize) -> Vec<std::ops::Range<usize>> {
+    let size = rows.div_ceil(threads.max(1)).max(1);
+    (0..rows)
+        .step_by(size)
+        .map(|start| start..(start + size).min(rows))
+        .collect()
+}
+
+// the rows of the relations that survived the semijoins so far: for every relation the reducer
+// changed, the indexes of its rows that are kept (in order). the reducer only reads the key
+// columns of these rows, the relations are materialized once after it (see select_rows).
+pub type Selections = HashMap<String, Vec<u32>>;
+
+// the semijoin of a step on the selected rows: the rows of the target whose key appears in
+// the source. large relations are hashed and probed by several threads, every thread takes a
+// range of the selected rows; the hash sets of the ranges are merged and the ranges of the
+// target stay in order, so the result is the same for any number of threads.
+fn semi_join_rows(
+    target: &RecordBatch,
+    target_rows: Option<&[u32]>,
+    source: &RecordBatch,
+    source_rows: Option<&[u32]>,
+    step: &SemiJoinStep,
+    null_policy: NullPolicy,
+    threads: usize,
+) -> Vec<u32> {
+    let source_columns: Vec<ArrayRef> = step
+        .keys
+        .iter()
+        .map(|key| source.column(key.source_column).clone())
+        .collect();
+    let target_columns: Vec<ArrayRef> = step
+        .keys
+        .iter()
+        .map(|key| target.column(key.target_column).clone())
+        .collect();
+    // the row of a position in the selection.
+    let row =
+        |rows: Option<&[u32]>, position: usize| rows.map_or(position, |r| r[position] as usize);
+    let source_len = source_rows.map_or(source.num_rows(), |rows| rows.len());
+    let target_len = target_rows.map_or(target.num_rows(), |rows| rows.len());
+    let build = |positions: std::ops::Range<usize>| -> HashSet<Vec<Value>> {
+        positions
+            .filter_map(|p| row_key(&source_columns, row(source_rows, p), null_policy))
+            .collect()
+    };
+    let probe = |positions: std::ops::Range<usize>, keys: &HashSet<Vec<Value>>| -> Vec<u32> {
+        positions
+            .map(|p| row(target_rows, p))
+            .filter(|&r| {
+                row_key(&target_columns, r, null_policy).is_some_and(|key| keys.contains(&key))
+            })
+            .map(|r| r as u32)
+            .collect()
+    };
+    if threads <= 1 || source_len.max(target_len) < PARALLEL_ROWS {
+        let keys = build(0..source_len);
+        return probe(0..target_len, &keys);
+    }
+    // the keys of the source.
+    let keys = std::thread::scope(|scope| {
+        let handles: Vec<_> = row_ranges(source_len, threads)
+            .into_iter()
+            .map(|positions| scope.spawn(move || build(positions)))
+            .collect();
+        let mut keys = HashSet::new();
+        for handle in handles {
+            keys.extend(handle.join().unwrap());
         }
+        keys
+    });
+    let keys = &keys;
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = row_ranges(target_len, threads)
+            .into_iter()
+            .map(|positions| scope.spawn(move || probe(positions, keys)))
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect()
+    })
+}
+
+// make a boolean array for a constant depending of the column datatype.
+// the constant is parsed to the type of the column, a NULL never equals a constant.
+// every type selects the values equal to the constant, as sql = does: a string constant
+// "Westmalle" does not select "Abdij Westmalle" (no substring match).
+pub fn make_boolean_array_constant(
+    relation: &RecordBatch,
+    column_index: usize,
+    value: &str,
+) -> BooleanArray {
+    match relation.column(column_index).data_type() {
+        DataType::Utf8 => make_boolean_array_string(relation, column_index, value),
+        DataType::Int64 => match value.parse::<i64>() {
+            Ok(value) => make_boolean_array_int64(relation, column_index, value),
+            Err(_) => BooleanArray::from(vec![false; relation.num_rows()]),
+        },
+        DataType::Float64 => match value.parse::<f64>() {
+            Ok(value) => make_boolean_array_float64(relation, column_index, value),
+            Err(_) => BooleanArray::from(vec![false; relation.num_rows()]),
+        },
+        data_type => panic!("Unsupported data type: {:?}", data_type),
     }
-    BooleanArray::from(result)
+}
+
+// make a boolean array that keeps the rows where two columns of the same relation are equal.
+// used for a variable that appears more than once in an atom. the columns are compared in
+// their common type, like the join keys; without one the selection is an error.
+pub fn make_boolean_array_columns(
+    relation: &RecordBatch,
+    column_index1: usize,
+    column_index2: usize,
+    null_policy: NullPolicy,
+    key_coercion: KeyCoercion,
+) -> Result<BooleanArray, Box<dyn Error>> {
+    let schema = relation.schema();
+    let (field1, field2) = (schema.field(column_index1), schema.field(column_index2));
+    let data_type =
+        common_type(field1.data_type(), field2.data_type(), key_coercion).ok_or(format!(
+            "Columns {} and {} of the same variable have types {:?} and {:?}",
+            field1.name(),
+            field2.name(),
+            field1.data_type(),
+            field2.data_type()
+        ))?;
+    let col1 = cast_key(relation.column(column_index1), &data_type)?;
+    let col2 = cast_key(relation.column(column_index2), &data_type)?;
+    let result = match col1.data_type() {
+        DataType::Utf8 => {
+            let values1 = col1.as_any().downcast_ref::<StringArray>().unwrap();
+            let values2 = col2.as_any().downcast_ref::<StringArray>().unwrap();
+            values1
+                .iter()
+                .zip(values2.iter())
+                .map(|(value1, value2)| null_policy.matches(value1, value2))
+                .collect::<Vec<_>>()
+        }
+        DataType::Int64 => {
+            let values1 = col1.as_any().downcast_ref::<Int64Array>().unwrap();
+            let values2 = col2.as_any().downcast_ref::<Int64Array>().unwrap();
+            values1
+                .iter()
+                .zip(values2.iter())
+                .map(|(value1, value2)| null_policy.matches(value1, value2))
+                .collect::<Vec<_>>()
+        }
+        DataType::Float64 => {
+            let values1 = col1.as_any().downcast_ref::<Float64Array>().unwrap();
+            let values2 = col2.as_any().downcast_ref::<Float64Array>().unwrap();
+            values1
+                .iter()
+                .zip(values2.iter())
+                .map(|(value1, value2)| null_policy.matches(value1, value2))
+                .collect::<Vec<_>>()
+        }
+        data_type => panic!("Unsupported data type: {:?}", data_type),
+    };
+    Ok(BooleanArray::from(result))
 }
 
 // make a boolean array for value depending of the column datatype:
+// the strings equal to the value, NULL is never equal.
 pub fn make_boolean_array_string(
     relation: &RecordBatch,
     column_index: usize,
@@ -226,7 +819,7 @@ pub fn make_boolean_array_string(
         .unwrap();
     let result = col
         .iter()
-        .map(|item| item.as_deref().map_or(false, |s| s.contains(value)))
+        .map(|item| item == Some(value))
         .collect::<Vec<_>>();
     BooleanArray::from(result)
 }
@@ -265,36 +858,605 @@ pub fn make_boolean_array_float64(
     BooleanArray::from(result)
 }
 
-pub fn reduce(infos: Vec<Vec<String>>, data: &mut HashMap<String, RecordBatch>) {
-    for info in infos {
-        // distribute the info from the vector
-        let key1 = info[0].clone();
-        let key2 = info[1].clone();
-        let column = info[2].as_str();
+// two steps conflict when one of them changes a relation the other one reads or changes:
+// they must run in the order of the program.
+fn conflict(first: &SemiJoinStep, second: &SemiJoinStep) -> bool {
+    first.target == second.target || first.target == second.source || first.source == second.target
+}
 
-        // get the required recordbatches
-        let record_batch1 = data.get(&key1);
-        let record_batch2 = data.get(&key2);
-        // get the required column indexes
-        let column_index1 = record_batch1
-            .unwrap()
-            .schema()
-            .index_of(column)
-            .unwrap_or(42);
-        let column_index2 = record_batch2
-            .unwrap()
-            .schema()
-            .index_of(column)
-            .unwrap_or(42);
-        // make the boolean array
-        let boolean_array = make_boolean_array(
-            record_batch1.unwrap(),
-            column_index1,
-            record_batch2.unwrap(),
-            column_index2,
+// the bloom filter pre-pass of a semijoin step: the selected rows of the target whose key may
+// be in the source. a rejected row is dangling for sure, so the exact semijoin on the passed
+// rows keeps the same rows as on all of them.
+fn bloom_filter_rows(
+    target: &RecordBatch,
+    target_rows: Option<&[u32]>,
+    source: &RecordBatch,
+    source_rows: Option<&[u32]>,
+    step: &SemiJoinStep,
+    null_policy: NullPolicy,
+    false_positive_rate: f64,
+) -> (Vec<u32>, BloomFilterReport) {
+    let source_columns: Vec<ArrayRef> = step
+        .keys
+        .iter()
+        .map(|key| source.column(key.source_column).clone())
+        .collect();
+    let target_columns: Vec<ArrayRef> = step
+        .keys
+        .iter()
+        .map(|key| target.column(key.target_column).clone())
+        .collect();
+    let source_rows: Vec<usize> = match source_rows {
+        Some(rows) => rows.iter().map(|&r| r as usize).collect(),
+        None => (0..source.num_rows()).collect(),
+    };
+    let mut filter = BloomFilter::new(source_rows.len(), false_positive_rate);
+    for row in source_rows {
+        if let Some(key) = row_key(&source_columns, row, null_policy) {
+            filter.insert(&key);
+        }
+    }
+    let passed: Vec<u32> = match target_rows {
+        Some(rows) => rows.to_vec(),
+        None => (0..target.num_rows() as u32).collect(),
+    }
+    .into_iter()
+    .filter(|&r| {
+        row_key(&target_columns, r as usize, null_policy).is_some_and(|key| filter.contains(&key))
+    })
+    .collect();
+    let report = BloomFilterReport {
+        false_positive_rate: filter.false_positive_rate(),
+        bits: filter.bits(),
+        hashes: filter.hashes(),
+        passed_rows: passed.len(),
+        false_positives: 0,
+    };
+    (passed, report)
+}
+
+// the exact semijoin after the bloom filter when it passed fewer target rows than the source
+// has: the keys of the passed rows are hashed instead of the source keys, the source is only
+// scanned. so the hash sets hold at most one key per passed row.
+fn semi_join_passed_rows(
+    target: &RecordBatch,
+    passed: &[u32],
+    source: &RecordBatch,
+    source_rows: Option<&[u32]>,
+    step: &SemiJoinStep,
+    null_policy: NullPolicy,
+) -> Vec<u32> {
+    let source_columns: Vec<ArrayRef> = step
+        .keys
+        .iter()
+        .map(|key| source.column(key.source_column).clone())
+        .collect();
+    let target_columns: Vec<ArrayRef> = step
+        .keys
+        .iter()
+        .map(|key| target.column(key.target_column).clone())
+        .collect();
+    let keys: Vec<Option<Vec<Value>>> = passed
+        .iter()
+        .map(|&r| row_key(&target_columns, r as usize, null_policy))
+        .collect();
+    let candidates: HashSet<&Vec<Value>> = keys.iter().flatten().collect();
+    let source_len = source_rows.map_or(source.num_rows(), |rows| rows.len());
+    let found: HashSet<Vec<Value>> = (0..source_len)
+        .map(|p| source_rows.map_or(p, |rows| rows[p] as usize))
+        .filter_map(|r| row_key(&source_columns, r, null_policy))
+        .filter(|key| candidates.contains(key))
+        .collect();
+    passed
+        .iter()
+        .zip(&keys)
+        .filter(|(_, key)| key.as_ref().is_some_and(|key| found.contains(key)))
+        .map(|(&r, _)| r)
+        .collect()
+}
+
+// run one semijoin step on the selected rows, the kept rows of the target and the report.
+// with a bloom filter pass and a large enough source the target goes through a bloom filter of
+// the source first.
+fn semi_join(
+    step: &SemiJoinStep,
+    data: &HashMap<String, RecordBatch>,
+    selections: &Selections,
+    null_policy: NullPolicy,
+    threads: usize,
+    bloom_filter: Option<BloomFilterPass>,
+) -> (Vec<u32>, SemiJoinReport) {
+    let start = Instant::now();
+    // get the required recordbatches and their selected rows
+    let target = &data[&step.target];
+    let source = &data[&step.source];
+    let target_rows = selections.get(&step.target).map(|rows| rows.as_slice());
+    let source_rows = selections.get(&step.source).map(|rows| rows.as_slice());
+    let input_rows = target_rows.map_or(target.num_rows(), |rows| rows.len());
+    let source_len = source_rows.map_or(source.num_rows(), |rows| rows.len());
+    let (kept, bloom_filter) = match bloom_filter {
+        Some(pass) if source_len >= pass.min_source_rows => {
+            let (passed, mut report) = bloom_filter_rows(
+                target,
+                target_rows,
+                source,
+                source_rows,
+                step,
+                null_policy,
+                pass.false_positive_rate,
+            );
+            let kept = if passed.len() < source_len {
+                semi_join_passed_rows(target, &passed, source, source_rows, step, null_policy)
+            } else {
+                semi_join_rows(
+                    target,
+                    Some(&passed),
+                    source,
+                    source_rows,
+                    step,
+                    null_policy,
+                    threads,
+                )
+            };
+            report.false_positives = report.passed_rows - kept.len();
+            (kept, Some(report))
+        }
+        _ => {
+            let kept = semi_join_rows(
+                target,
+                target_rows,
+                source,
+                source_rows,
+                step,
+                null_policy,
+                threads,
+            );
+            (kept, None)
+        }
+    };
+    let report = SemiJoinReport {
+        step: step.clone(),
+        input_rows,
+        source_rows: source_len,
+        output_rows: kept.len(),
+        bloom_filter,
+        elapsed_us: elapsed_us(start),
+    };
+    (kept, report)
+}
+
+// semijoin reducer, returns false as soon as a relation becomes empty
+// (the query has no answers then and the remaining semijoins are skipped).
+// the relations are not changed: the kept rows of every target are in the selections.
+// every executed step is reported with its row counts and elapsed time.
+// the steps run in rounds on up to threads threads: a step joins a round when it conflicts
+// with no earlier step that has not run yet (see conflict). steps with the same target
+// conflict, so the semijoins of the children of a parent run in separate rounds; steps on
+// disjoint relations, e.g. in the subtrees of different children, run together. so every step
+// sees the same rows as when the steps run one after the other, and the selections and the
+// reports (in program order) do not depend on the threads, except that when a relation
+// becomes empty the other steps of its round have run too.
+// with a bloom filter pass every step on a large enough source runs a bloom filter pre-pass
+// (see bloom_filter_rows).
+pub fn reduce(
+    steps: &[SemiJoinStep],
+    data: &HashMap<String, RecordBatch>,
+    selections: &mut Selections,
+    null_policy: NullPolicy,
+    threads: usize,
+    bloom_filter: Option<BloomFilterPass>,
+    reports: &mut Vec<SemiJoinReport>,
+) -> bool {
+    let threads = threads.max(1);
+    let mut pending: Vec<usize> = (0..steps.len()).collect();
+    let mut done: Vec<(usize, SemiJoinReport)> = Vec::new();
+    let mut non_empty = true;
+    while non_empty && !pending.is_empty() {
+        let round: Vec<usize> = pending
+            .iter()
+            .enumerate()
+            .filter(|(position, &s)| {
+                pending[..*position]
+                    .iter()
+                    .all(|&earlier| !conflict(&steps[earlier], &steps[s]))
+            })
+            .map(|(_, &s)| s)
+            .take(threads)
+            .collect();
+        pending.retain(|s| !round.contains(s));
+        // the threads left over hash and probe inside the steps.
+        let step_threads = threads / round.len();
+        let selected = &*selections;
+        let results: Vec<(Vec<u32>, SemiJoinReport)> = if round.len() == 1 {
+            let step = &steps[round[0]];
+            vec![semi_join(
+                step,
+                data,
+                selected,
+                null_policy,
+                step_threads,
+                bloom_filter,
+            )]
+        } else {
+            std::thread::scope(|scope| {
+                let handles: Vec<_> = round
+                    .iter()
+                    .map(|&s| {
+                        scope.spawn(move || {
+                            semi_join(
+                                &steps[s],
+                                data,
+                                selected,
+                                null_policy,
+                                step_threads,
+                                bloom_filter,
+                            )
+                        })
+                    })
+                    .collect();
+                handles
+                    .into_iter()
+                    .map(|handle| handle.join().unwrap())
+                    .collect()
+            })
+        };
+        for (&s, (kept, report)) in round.iter().zip(results) {
+            non_empty = non_empty && !kept.is_empty();
+            selections.insert(steps[s].target.clone(), kept);
+            done.push((s, report));
+        }
+    }
+    done.sort_by_key(|(s, _)| *s);
+    reports.extend(done.into_iter().map(|(_, report)| report));
+    non_empty
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::join::make_record_batch;
+    use crate::queries::Term;
+    use arrow::datatypes::Field;
+    use std::sync::Arc;
+
+    // a boolean query over atoms of variables.
+    fn query(atoms: &[(&'static str, &[&'static str])]) -> ConjunctiveQuery {
+        let body_atoms = atoms
+            .iter()
+            .map(|&(name, variables)| Atom {
+                name,
+                terms: variables
+                    .iter()
+                    .map(|&v| &*Box::leak(Box::new(Term::Variable(v))))
+                    .collect(),
+            })
+            .collect();
+        ConjunctiveQuery {
+            head_atom: Atom {
+                name: "Answer",
+                terms: vec![],
+            },
+            body_atoms,
+        }
+    }
+
+    // a node with its children.
+    fn node(
+        relation: &str,
+        common_variables: &[&'static str],
+        children: Vec<JoinTreeNode>,
+    ) -> JoinTreeNode {
+        let mut node = JoinTreeNode::new(relation.to_string(), common_variables.to_vec());
+        for child in children {
+            node.add_child(child);
+        }
+        node
+    }
+
+    // a relation of Int64 columns from its rows, None is NULL.
+    fn relation(columns: &[&str], rows: &[Vec<Option<i64>>]) -> RecordBatch {
+        let fields = columns
+            .iter()
+            .map(|&column| Field::new(column, DataType::Int64, true))
+            .collect();
+        let arrays = (0..columns.len())
+            .map(|c| Arc::new(rows.iter().map(|row| row[c]).collect::<Int64Array>()) as ArrayRef)
+            .collect();
+        make_record_batch(fields, arrays, rows.len())
+    }
+
+    // target := target ⋉ source on the first column of both.
+    fn step(target: &str, source: &str) -> SemiJoinStep {
+        SemiJoinStep {
+            target: target.to_string(),
+            source: source.to_string(),
+            keys: vec![KeyColumn {
+                variable: "a".to_string(),
+                target_column: 0,
+                source_column: 0,
+            }],
+            direction: Direction::BottomUp,
+        }
+    }
+
+    fn chain() -> ConjunctiveQuery {
+        query(&[("R", &["a", "b"]), ("S", &["b", "c"]), ("T", &["c", "d"])])
+    }
+
+    #[test]
+    fn valid_join_forest() {
+        let forest = vec![node(
+            "R",
+            &["b"],
+            vec![node("S", &["b", "c"], vec![node("T", &["c"], vec![])])],
+        )];
+        assert_eq!(validate_join_forest(&chain(), &forest), Ok(()));
+        let forest = build_join_forest(&chain(), &Statistics::default());
+        assert_eq!(validate_join_forest(&chain(), &forest), Ok(()));
+    }
+
+    #[test]
+    fn running_intersection_is_broken() {
+        // b is in R and S, but not in T between them.
+        let forest = vec![node(
+            "R",
+            &["b"],
+            vec![node("T", &["c"], vec![node("S", &["b", "c"], vec![])])],
+        )];
+        assert_eq!(
+            validate_join_forest(&chain(), &forest),
+            Err(JoinTreeError::RunningIntersection {
+                variable: "b".to_string(),
+                nodes: vec!["R".to_string(), "S".to_string()],
+            })
+        );
+        // c is in two trees.
+        let forest = vec![
+            node("R", &["b"], vec![node("S", &["b", "c"], vec![])]),
+            node("T", &["c"], vec![]),
+        ];
+        assert_eq!(
+            validate_join_forest(&chain(), &forest),
+            Err(JoinTreeError::RunningIntersection {
+                variable: "c".to_string(),
+                nodes: vec!["S".to_string(), "T".to_string()],
+            })
+        );
+    }
+
+    #[test]
+    fn atom_is_missing_or_duplicated() {
+        let forest = vec![node("R", &["b"], vec![node("S", &["b", "c"], vec![])])];
+        assert_eq!(
+            validate_join_forest(&chain(), &forest),
+            Err(JoinTreeError::Atom {
+                relation: "T".to_string(),
+                nodes: 0,
+            })
+        );
+        let forest = vec![
+            node(
+                "R",
+                &["b"],
+                vec![node("S", &["b", "c"], vec![node("T", &["c"], vec![])])],
+            ),
+            node("S", &["b", "c"], vec![]),
+        ];
+        assert_eq!(
+            validate_join_forest(&chain(), &forest),
+            Err(JoinTreeError::Atom {
+                relation: "S".to_string(),
+                nodes: 2,
+            })
+        );
+    }
+
+    #[test]
+    fn bloom_filter_keeps_the_exact_semijoin() {
+        let target = relation(
+            &["a"],
+            &(0..3000)
+                .map(|a| vec![Some(a)])
+                .chain([vec![None]])
+                .collect::<Vec<_>>(),
+        );
+        // a source with more rows than the filter passes (the passed rows are hashed), and
+        // one with fewer (the source keys are hashed).
+        let large = (0..2000)
+            .step_by(2)
+            .chain(10_001..12_001)
+            .map(|a| vec![Some(a)])
+            .chain([vec![None]])
+            .collect::<Vec<_>>();
+        let small = vec![vec![Some(0)], vec![Some(2)], vec![Some(4)]];
+        let pass = BloomFilterPass {
+            false_positive_rate: 0.01,
+            min_source_rows: 0,
+        };
+        for source in [large, small] {
+            let data: HashMap<String, RecordBatch> = [
+                ("T".to_string(), target.clone()),
+                ("S".to_string(), relation(&["a"], &source)),
+            ]
+            .into();
+            let mut selections = Selections::new();
+            for selected in [false, true] {
+                if selected {
+                    selections.insert("T".to_string(), (0..3001).step_by(3).collect());
+                    selections.insert("S".to_string(), (0..source.len() as u32 - 1).collect());
+                }
+                let step = step("T", "S");
+                let (exact, exact_report) =
+                    semi_join(&step, &data, &selections, NullPolicy::Sql, 1, None);
+                let (kept, report) =
+                    semi_join(&step, &data, &selections, NullPolicy::Sql, 1, Some(pass));
+                assert_eq!(kept, exact);
+                assert!(exact_report.bloom_filter.is_none());
+                let bloom = report.bloom_filter.unwrap();
+                assert_eq!(report.input_rows, exact_report.input_rows);
+                assert_eq!(report.output_rows, kept.len());
+                assert!(bloom.passed_rows <= report.input_rows);
+                assert_eq!(
+                    bloom.passed_rows - bloom.false_positives,
+                    report.output_rows
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn bloom_filter_waits_for_large_sources() {
+        let data: HashMap<String, RecordBatch> = [
+            (
+                "T".to_string(),
+                relation(&["a"], &[vec![Some(1)], vec![Some(2)]]),
+            ),
+            ("S".to_string(), relation(&["a"], &[vec![Some(1)]])),
+        ]
+        .into();
+        let pass = BloomFilterPass {
+            false_positive_rate: 0.01,
+            min_source_rows: 2,
+        };
+        let (kept, report) = semi_join(
+            &step("T", "S"),
+            &data,
+            &Selections::new(),
+            NullPolicy::Sql,
+            1,
+            Some(pass),
         );
-        // filter relation1
-        let filtered_relation1 = filter_record_batch(record_batch1.unwrap(), &boolean_array);
-        data.insert(key1.clone(), filtered_relation1.unwrap());
+        assert_eq!(kept, vec![0]);
+        assert!(report.bloom_filter.is_none());
+    }
+
+    #[test]
+    fn semijoin_on_null_keys() {
+        let data: HashMap<String, RecordBatch> = [
+            (
+                "T".to_string(),
+                relation(
+                    &["a"],
+                    &[vec![Some(1)], vec![None], vec![Some(2)], vec![None]],
+                ),
+            ),
+            (
+                "S".to_string(),
+                relation(&["a"], &[vec![Some(1)], vec![None]]),
+            ),
+        ]
+        .into();
+        let selections = Selections::new();
+        let step = step("T", "S");
+        let (kept, _) = semi_join(&step, &data, &selections, NullPolicy::Sql, 1, None);
+        assert_eq!(kept, vec![0]);
+        let (kept, _) = semi_join(&step, &data, &selections, NullPolicy::NullsEqual, 1, None);
+        assert_eq!(kept, vec![0, 1, 3]);
+    }
+
+    #[test]
+    fn selections_on_nulls() {
+        let numbers = relation(
+            &["a", "b"],
+            &[
+                vec![Some(1), Some(1)],
+                vec![None, None],
+                vec![Some(1), Some(2)],
+                vec![None, Some(1)],
+            ],
+        );
+        // a NULL never equals a constant.
+        let selected = make_boolean_array_constant(&numbers, 0, "1");
+        assert_eq!(selected, BooleanArray::from(vec![true, false, true, false]));
+        // a repeated variable: two NULLs are only equal under NullsEqual.
+        let equal = |null_policy| {
+            make_boolean_array_columns(&numbers, 0, 1, null_policy, KeyCoercion::default()).unwrap()
+        };
+        assert_eq!(equal(NullPolicy::Sql).true_count(), 1);
+        assert_eq!(equal(NullPolicy::NullsEqual).true_count(), 2);
+
+        // a string constant selects equal strings only.
+        let strings = make_record_batch(
+            vec![Field::new("city", DataType::Utf8, true)],
+            vec![Arc::new(StringArray::from(vec![
+                Some("Westmalle"),
+                Some("Abdij Westmalle"),
+                None,
+            ])) as ArrayRef],
+            3,
+        );
+        let selected = make_boolean_array_constant(&strings, 0, "Westmalle");
+        assert_eq!(selected, BooleanArray::from(vec![true, false, false]));
+    }
+
+    #[test]
+    fn threads_give_the_same_reduction() {
+        // R has three children, of which S and T have a child too.
+        let query = query(&[
+            ("R", &["a", "b", "c"]),
+            ("S", &["a", "d"]),
+            ("T", &["b", "e"]),
+            ("U", &["c"]),
+            ("V", &["d"]),
+            ("W", &["e"]),
+        ]);
+        let int_rows = |rows: &[&[i64]]| -> Vec<Vec<Option<i64>>> {
+            rows.iter()
+                .map(|row| row.iter().map(|&v| Some(v)).collect())
+                .collect()
+        };
+        let data: HashMap<String, RecordBatch> = [
+            (
+                "R",
+                relation(
+                    &["a", "b", "c"],
+                    &int_rows(&[&[1, 1, 1], &[2, 2, 2], &[3, 3, 3], &[4, 4, 4]]),
+                ),
+            ),
+            (
+                "S",
+                relation(
+                    &["a", "d"],
+                    &int_rows(&[&[1, 1], &[2, 2], &[3, 3], &[5, 5]]),
+                ),
+            ),
+            (
+                "T",
+                relation(
+                    &["b", "e"],
+                    &int_rows(&[&[1, 1], &[2, 2], &[4, 4], &[6, 6]]),
+                ),
+            ),
+            ("U", relation(&["c"], &int_rows(&[&[1], &[2], &[3], &[4]]))),
+            ("V", relation(&["d"], &int_rows(&[&[1], &[2], &[5]]))),
+            ("W", relation(&["e"], &int_rows(&[&[1], &[4], &[6]]))),
+        ]
+        .into_iter()
+        .map(|(name, relation)| (name.to_string(), relation))
+        .collect();
+        let program = join_tree(&query, &Statistics::default());
+        let run = |threads| {
+            let mut selections = Selections::new();
+            let mut reports = Vec::new();
+            let non_empty = reduce(
+                &program.steps,
+                &data,
+                &mut selections,
+                NullPolicy::Sql,
+                threads,
+                None,
+                &mut reports,
+            );
+            let reports: Vec<(SemiJoinStep, usize, usize, usize)> = reports
+                .into_iter()
+                .map(|r| (r.step, r.input_rows, r.source_rows, r.output_rows))
+                .collect();
+            (non_empty, selections, reports)
+        };
+        let (non_empty, selections, reports) = run(1);
+        assert!(non_empty);
+        assert_eq!(selections["R"], vec![0]);
+        assert_eq!(reports.len(), program.steps.len());
+        assert_eq!(run(4), (non_empty, selections, reports));
     }
 }
@@ -0,0 +1,101 @@
+// Query-driven loading: only the relations of the body atoms are read, and of a relation only
+// the columns the queries use are decoded. A column is used when its term is a constant, a
+// head variable, a variable of another atom, or a variable that appears more than once in the
+// atom. The other columns are kept as NULL columns (type Null, no buffers), so the columns of
+// a relation stay at the positions of the terms of its atoms.
+
+use std::collections::{BTreeSet, HashMap};
+use std::error::Error;
+use std::fs::File;
+use std::sync::Arc;
+
+use arrow::{
+    array::{ArrayRef, NullArray},
+    compute::concat_batches,
+    csv::{self, ReaderBuilder},
+    datatypes::{DataType, Field},
+    record_batch::RecordBatch,
+};
+
+use crate::gyo::acyclic_join_forest;
+use crate::join::make_record_batch;
+use crate::queries::{ConjunctiveQuery, Term};
+
+// the columns the queries use of every relation in their bodies, in order.
+// a cyclic query may join on any variable of a bag, all its variable columns are used.
+pub fn needed_columns(queries: &[&ConjunctiveQuery]) -> HashMap<String, Vec<usize>> {
+    let mut needed: HashMap<String, BTreeSet<usize>> = HashMap::new();
+    for query in queries {
+        let head = query.head_atom.variables();
+        let cyclic = acyclic_join_forest(query).is_none();
+        for (a, atom) in query.body_atoms.iter().enumerate() {
+            let columns = needed.entry(atom.name.to_string()).or_default();
+            for (index, term) in atom.terms.iter().enumerate() {
+                let used = match term {
+                    Term::Constant(_) => true,
+                    Term::Variable(name) => {
+                        cyclic
+                            || head.contains(name)
+                            || atom.terms.iter().filter(|t| t == &term).count() > 1
+                            || query
+                                .body_atoms
+                                .iter()
+                                .enumerate()
+                                .any(|(b, other)| b != a && other.variables().contains(name))
+                    }
+                };
+                if used {
+                    columns.insert(index);
+                }
+            }
+        }
+    }
+    needed
+        .into_iter()
+        .map(|(name, columns)| (name, columns.into_iter().collect()))
+        .collect()
+}
+
+// read a csv file with a header into one record batch (all its batches), decoding only the
+// given columns. the other columns are NULL columns.
+pub fn load_relation(file_path: &str, columns: &[usize]) -> Result<RecordBatch, Box<dyn Error>> {
+    let schema = csv::infer_schema_from_files(&[file_path.to_string()], b',', None, true)?;
+    let schema = Arc::new(schema);
+    let columns: Vec<usize> = columns
+        .iter()
+        .copied()
+        .filter(|&c| c < schema.fields().len())
+        .collect();
+    let file = File::open(file_path)?;
+    let reader = ReaderBuilder::new(schema.clone())
+        .has_header(true)
+        .with_projection(columns.clone())
+        .build(file)?;
+    let batches = reader.collect::<Result<Vec<_>, _>>()?;
+    let projected = Arc::new(schema.project(&columns)?);
+    let decoded = concat_batches(&projected, &batches)?;
+    let rows = decoded.num_rows();
+
+    let mut fields = Vec::new();
+    let mut arrays: Vec<ArrayRef> = Vec::new();
+    for (index, field) in schema.fields().iter().enumerate() {
+        match columns.iter().position(|&c| c == index) {
+            Some(position) => {
+                fields.push(field.as_ref().clone());
+                arrays.push(decoded.column(position).clone());
+            }
+            None => {
+                fields.push(Field::new(field.name(), DataType::Null, true));
+                arrays.push(Arc::new(NullArray::new(rows)));
+            }
+        }
+    }
+    println!(
+        "Successfully read {} rows ({} of {} columns) from file: {}",
+        rows,
+        columns.len(),
+        schema.fields().len(),
+        file_path
+    );
+    Ok(make_record_batch(fields, arrays, rows))
+}
@@ -1,64 +1,168 @@
-use arrow::csv::{self, ReaderBuilder};
-use arrow::datatypes::Schema;
-use arrow::record_batch::RecordBatch;
 use std::collections::HashMap;
 use std::error::Error;
-use std::fs::File;
-use std::sync::Arc;
 
+mod bloom;
+use bloom::BloomFilterPass;
+mod coercion;
+use coercion::KeyCoercion;
+mod cost;
+use cost::Statistics;
+mod csvout;
+mod decomposition;
+use csvout::write_record_batch_to_csv;
+use decomposition::decompose;
+mod enumeration;
+mod explain;
+mod gyo;
+use gyo::{acyclic_join_forest, acyclic_test, hypergraph_to_dot};
+mod hypergraph;
+mod join;
+use join::NullPolicy;
+mod jointrees;
+use jointrees::{all_join_forests, build_join_forest, semi_join_program};
+mod loader;
+use loader::{load_relation, needed_columns};
 mod queries;
 use queries::{
-    create_cq1, create_cq2, create_cq3, create_cq4, create_cq5, 
+    create_cq1, create_cq2, create_cq3, create_cq4, create_cq5, create_cq6, create_cq7,
     create_cyclic_example_query, create_example_query,
 };
 
+mod wcoj;
 mod yannakaki;
-use yannakaki::yannakaki;
-// takes a filename and returns a Arrow recordbatch
-fn process_file(file_path: &str, schema: Arc<Schema>) -> Result<RecordBatch, Box<dyn Error>> {
-    let file = File::open(file_path)?;
-    let mut csv = ReaderBuilder::new(schema).has_header(true).build(file)?;
-    let batch = csv.next().ok_or("No record batch found")??;
+use yannakaki::{
+    statistics, yannakaki, yannakaki_enumerate, yannakaki_explain, yannakaki_join_forest, Answer,
+    CyclicStrategy, Database, Options,
+};
+// how reports are printed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Text,
+    Json,
+}
 
-    println!("Successfully read a batch from file: {}", file_path);
+// what the command line asks for.
+struct Args {
+    query_names: Vec<String>,
+    options: Options,
+    // print the semijoin program of every query.
+    plan: Option<Format>,
+    // explain the evaluation of every query.
+    explain: Option<Format>,
+    // print the join tree of every query as an ascii tree.
+    tree: bool,
+    // print the hypergraph and the join tree of every query in graphviz dot format.
+    dot: bool,
+    // print every join tree of every (acyclic) query, with its estimated cost and its answer.
+    join_trees: bool,
+    // print the first answers of every (free-connex) query, enumerated one by one.
+    enumerate: Option<usize>,
+    // print the acyclicity class, the widths and the cycles of every query.
+    analyze: bool,
+}
 
-    Ok(batch)
+// read the command line: the names of the queries to run and the options.
+// dim [query...] [--nulls-equal] [--key-coercion strict|numeric|lenient]
+//     [--cyclic decomposition|generic-join]
+//     [--plan] [--plan-json] [--explain] [--explain-json] [--tree] [--dot] [--join-trees]
+//     [--enumerate n] [--threads n] [--bloom-filter rate] [--bloom-filter-rows n] [--analyze]
+fn parse_args(args: &[String]) -> Result<Args, Box<dyn Error>> {
+    let mut query_names = Vec::new();
+    let mut options = Options::default();
+    let mut plan = None;
+    let mut explain = None;
+    let mut tree = false;
+    let mut dot = false;
+    let mut join_trees = false;
+    let mut enumerate = None;
+    let mut analyze = false;
+    let mut bloom_filter_rows = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--nulls-equal" => options.null_policy = NullPolicy::NullsEqual,
+            "--cyclic" => {
+                options.cyclic_strategy = match args.next().map(|value| value.as_str()) {
+                    Some("decomposition") => CyclicStrategy::Decomposition,
+                    Some("generic-join") => CyclicStrategy::GenericJoin,
+                    value => return Err(format!("Unknown cyclic strategy: {:?}", value).into()),
+                }
+            }
+            "--plan" => plan = Some(Format::Text),
+            "--plan-json" => plan = Some(Format::Json),
+            "--explain" => explain = Some(Format::Text),
+            "--explain-json" => explain = Some(Format::Json),
+            "--tree" => tree = true,
+            "--dot" => dot = true,
+            "--join-trees" => join_trees = true,
+            "--analyze" => analyze = true,
+            "--enumerate" => {
+                let limit = args.next().ok_or("Missing number of answers to enumerate")?;
+                enumerate = Some(limit.parse()?);
+            }
+            "--threads" => {
+                let threads = args.next().ok_or("Missing number of threads")?;
+                options.threads = threads.parse()?;
+                if options.threads == 0 {
+                    return Err("The number of threads must be at least 1".into());
+                }
+            }
+            "--bloom-filter" => {
+                let rate: f64 = args.next().ok_or("Missing false positive rate")?.parse()?;
+                if !(rate > 0.0 && rate < 0.5) {
+                    return Err(format!("Invalid false positive rate: {}", rate).into());
+                }
+                options.bloom_filter = Some(BloomFilterPass::new(rate));
+            }
+            "--bloom-filter-rows" => {
+                let rows = args.next().ok_or("Missing number of rows")?.parse()?;
+                bloom_filter_rows = Some(rows);
+            }
+            "--key-coercion" => {
+                options.key_coercion = match args.next().map(|value| value.as_str()) {
+                    Some("strict") => KeyCoercion::Strict,
+                    Some("numeric") => KeyCoercion::Numeric,
+                    Some("lenient") => KeyCoercion::Lenient,
+                    value => return Err(format!("Unknown key coercion: {:?}", value).into()),
+                }
+            }
+            flag if flag.starts_with("--") => return Err(format!("Unknown option: {}", flag).into()),
+            name => query_names.push(name.to_string()),
+        }
+    }
+    if let (Some(pass), Some(rows)) = (&mut options.bloom_filter, bloom_filter_rows) {
+        pass.min_source_rows = rows;
+    }
+    if query_names.is_empty() {
+        query_names.push("cq4".to_string());
+    }
+    Ok(Args {
+        query_names,
+        options,
+        plan,
+        explain,
+        tree,
+        dot,
+        join_trees,
+        enumerate,
+        analyze,
+    })
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
-    // Load the data.
-    let beers = "./data/beers.csv";
-    let breweries = "./data/breweries.csv";
-    let categories = "./data/categories.csv";
-    let locations = "./data/locations.csv";
-    let styles = "./data/styles.csv";
-    let data = vec![beers, breweries, categories, locations, styles];
-    let keys = vec![
-        "Beers".to_string(),
-        "Breweries".to_string(),
-        "Categories".to_string(),
-        "Locations".to_string(),
-        "Styles".to_string(),
-    ];
-
-    // Create a HashMap to store RecordBatches
-    let mut record_batch_map: HashMap<String, RecordBatch> = HashMap::new();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let Args {
+        query_names,
+        options,
+        plan,
+        explain,
+        tree,
+        dot,
+        join_trees,
+        enumerate,
+        analyze,
+    } = parse_args(&args)?;
 
-    for (file_path, key) in data.iter().zip(keys.iter()) {
-        let schema = match csv::infer_schema_from_files(&[file_path.to_string()], b',', None, true)
-        {
-            Ok(schema) => schema,
-            Err(error) => {
-                eprintln!("An error occurred: {:?}", error);
-                Schema::empty()
-            }
-        };
-
-        // Call process_file and store the returned RecordBatch in the HashMap with the relation name as key
-        let batch = process_file(file_path, Arc::new(schema))?;
-        record_batch_map.insert(key.clone(), batch);
-    }
-    println!("rbm: {:?}", record_batch_map);
     // make all queries
     let query = create_example_query();
     let cquery = create_cyclic_example_query();
@@ -67,20 +171,171 @@ fn main() -> Result<(), Box<dyn Error>> {
     let cq3 = create_cq3();
     let cq4 = create_cq4();
     let cq5 = create_cq5();
+    let cq6 = create_cq6();
+    let cq7 = create_cq7();
+    let queries = [
+        ("example", &query),
+        ("cyclic", &cquery),
+        ("cq1", &cq1),
+        ("cq2", &cq2),
+        ("cq3", &cq3),
+        ("cq4", &cq4),
+        ("cq5", &cq5),
+        ("cq6", &cq6),
+        ("cq7", &cq7),
+    ];
+    let mut selected = Vec::new();
+    for query_name in &query_names {
+        let (name, query) = queries
+            .iter()
+            .find(|(name, _)| name == query_name)
+            .ok_or(format!("Unknown query: {}", query_name))?;
+        selected.push((*name, *query));
+    }
+
+    // Load the data: only the relations and the columns the requested queries use.
+    let data = [
+        ("Beers", "./data/beers.csv"),
+        ("Breweries", "./data/breweries.csv"),
+        ("Categories", "./data/categories.csv"),
+        ("Locations", "./data/locations.csv"),
+        ("Styles", "./data/styles.csv"),
+    ];
+    let needed = needed_columns(&selected.iter().map(|(_, query)| *query).collect::<Vec<_>>());
+
+    // Create a HashMap to store RecordBatches
+    let mut record_batch_map: Database = HashMap::new();
+
+    for (key, file_path) in data {
+        let Some(columns) = needed.get(key) else {
+            continue;
+        };
+        let batch = load_relation(file_path, columns)?;
+        record_batch_map.insert(key.to_string(), batch);
+    }
+    println!("rbm: {:?}", record_batch_map);
+    if let Some(pass) = options.bloom_filter {
+        if record_batch_map
+            .values()
+            .all(|batch| batch.num_rows() < pass.min_source_rows)
+        {
+            println!(
+                "Warning: no relation has {} rows, the bloom filter pre-pass will not run",
+                pass.min_source_rows
+            );
+        }
+    }
 
-    /*
-    Tests i query is acyclic
-    acyclic_test(&query);
-    acyclic_test(&cq1);
-    acyclic_test(&cq2);
-    acyclic_test(&cq3);
-    acyclic_test(&cq4);
-    acyclic_test(&cq5);
-    acyclic_test(&cquery);
-     */
+    // Tests if the queries are acyclic
+    if analyze {
+        let sizes: HashMap<String, usize> = record_batch_map
+            .iter()
+            .map(|(name, batch)| (name.clone(), batch.num_rows()))
+            .collect();
+        for (_, query) in &selected {
+            acyclic_test(query, &sizes);
+        }
+    }
 
-    // run yannakaki 
-    yannakaki(&cq4, &mut record_batch_map);
+    // print the structure of the queries, only then the views of the queries are counted.
+    if tree || dot || join_trees || plan.is_some() {
+        for (name, query) in &selected {
+            // the structure of a query without data (the cyclic example) needs no statistics.
+            let loaded = query
+                .body_atoms
+                .iter()
+                .all(|atom| record_batch_map.contains_key(atom.name));
+            let statistics = if loaded {
+                statistics(query, &record_batch_map, options)?
+            } else {
+                Statistics::default()
+            };
+            let join_forest = build_join_forest(query, &statistics);
+            if tree {
+                for root in &join_forest {
+                    print!("join tree of {}:\n{}", name, root.to_ascii());
+                }
+            }
+            if dot {
+                print!("{}", hypergraph_to_dot(query));
+                for root in &join_forest {
+                    print!("{}", root.to_dot());
+                }
+            }
+            if join_trees {
+                let forests = all_join_forests(query, &statistics);
+                println!("join trees of {}: {}", name, forests.len());
+                for (forest, cost) in &forests {
+                    let answer = yannakaki_join_forest(query, &record_batch_map, options, forest)?;
+                    let answer = match answer {
+                        Answer::Boolean(answer) => answer.to_string(),
+                        Answer::Relation(result) => format!("{} rows", result.num_rows()),
+                    };
+                    println!("estimated cost {:.0}, answer {}:", cost, answer);
+                    for root in forest {
+                        print!("{}", root.to_ascii());
+                    }
+                }
+            }
+            // a cyclic query has no join forest, its program runs on the bags of a decomposition.
+            let program = match acyclic_join_forest(query) {
+                Some(_) => semi_join_program(query, &join_forest),
+                None => decompose(query).semi_join_program(),
+            };
+            match plan {
+                Some(Format::Text) => print!("semijoin program of {}:\n{}", name, program),
+                Some(Format::Json) => println!("{}", program.to_json()),
+                None => {}
+            }
+        }
+    }
+    let database = &record_batch_map;
+    // page through the answers instead of computing them all.
+    if let Some(limit) = enumerate {
+        for (name, query) in &selected {
+            for answer in yannakaki_enumerate(query, database, options)?.take(limit) {
+                let values: Vec<String> = answer.iter().map(|value| value.to_string()).collect();
+                println!("{}: {}", name, values.join(","));
+            }
+        }
+        return Ok(());
+    }
+    // the queries share the loaded database and run concurrently.
+    let answers: Vec<_> = std::thread::scope(|scope| {
+        let handles: Vec<_> = selected
+            .iter()
+            .map(|(name, query)| {
+                scope.spawn(move || {
+                    let answer = match explain {
+                        Some(_) => yannakaki_explain(query, database, options)
+                            .map(|(answer, report)| (answer, Some(report))),
+                        None => yannakaki(query, database, options).map(|answer| (answer, None)),
+                    };
+                    let answer = answer.map_err(|e| e.to_string());
+                    (*name, answer)
+                })
+            })
+            .collect();
+        handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+    });
+    for (name, answer) in answers {
+        let (answer, report) = answer?;
+        match (explain, report) {
+            (Some(Format::Text), Some(report)) => print!("{}", report),
+            (Some(Format::Json), Some(report)) => println!("{}", report.to_json()),
+            _ => {}
+        }
+        match answer {
+            Answer::Boolean(answer) => println!("{}: {}", name, answer),
+            // write to csv, every query gets its own file when more than one query runs
+            Answer::Relation(result) if selected.len() == 1 => {
+                write_record_batch_to_csv(&result, "output")
+            }
+            Answer::Relation(result) => {
+                write_record_batch_to_csv(&result, &format!("output_{}", name))
+            }
+        }
+    }
 
     Ok(())
 }
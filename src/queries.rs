@@ -1,5 +1,7 @@
 // queries.rs
 
+use std::fmt;
+
 // Query data structure:
 // Define a struct to represent a term, which can be a constant or a Utf8String.
 #[derive(Debug, PartialEq, Eq, Hash, Clone)]
@@ -14,6 +16,22 @@ pub struct Atom {
     pub terms: Vec<&'static Term>,
 }
 
+impl Atom {
+    // the distinct variables of the atom, in order of appearance.
+    // after binding, the relation of the atom has one column per variable in this order.
+    pub fn variables(&self) -> Vec<&'static str> {
+        let mut names: Vec<&'static str> = Vec::new();
+        for term in &self.terms {
+            if let Term::Variable(name) = term {
+                if !names.contains(name) {
+                    names.push(name);
+                }
+            }
+        }
+        names
+    }
+}
+
 // Define a struct to represent a conjunctive query.
 #[derive(Debug)]
 pub struct ConjunctiveQuery {
@@ -21,6 +39,30 @@ pub struct ConjunctiveQuery {
     pub body_atoms: Vec<Atom>,
 }
 
+// print queries in datalog notation: Answer(x) :- R(x, y), S(y, 'c').
+impl fmt::Display for Term {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Term::Constant(value) => write!(f, "'{}'", value),
+            Term::Variable(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+impl fmt::Display for Atom {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let terms: Vec<String> = self.terms.iter().map(|term| term.to_string()).collect();
+        write!(f, "{}({})", self.name, terms.join(", "))
+    }
+}
+
+impl fmt::Display for ConjunctiveQuery {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let body: Vec<String> = self.body_atoms.iter().map(|atom| atom.to_string()).collect();
+        write!(f, "{} :- {}", self.head_atom, body.join(", "))
+    }
+}
+
 // the rest of the code exist of functions to generate the queries
 // this are all the possible columns used for the queries, 
 // recordbatch is indexed on index extracted from column name.
@@ -342,6 +384,88 @@ pub fn create_cq5() -> ConjunctiveQuery {
     }
 }
 
+// cq6
+// the beers of one brewery with every category: the atoms share no variables,
+// so the answer is a cross product.
+pub fn create_cq6() -> ConjunctiveQuery {
+    // only in -- beers --
+    let beer_id = &Term::Variable("beer_id");
+    let brew_id = &Term::Constant("478");
+    let beer = &Term::Variable("beer");
+    let abv = &Term::Variable("abv");
+    let ibu = &Term::Variable("ibu");
+    let ounces = &Term::Variable("ounces");
+    let style = &Term::Variable("style");
+    let style2 = &Term::Variable("style2");
+    // only in -- categories --
+    let cat_id = &Term::Variable("cat_id");
+    let cat_name = &Term::Variable("cat_name");
+
+    let beers = Atom {
+        name: "Beers",
+        terms: vec![beer_id, brew_id, beer, abv, ibu, ounces, style, style2],
+    };
+
+    let categories = Atom {
+        name: "Categories",
+        terms: vec![cat_id, cat_name],
+    };
+
+    let answer = Atom {
+        name: "Answer",
+        terms: vec![beer, cat_name],
+    };
+
+    ConjunctiveQuery {
+        head_atom: answer,
+        body_atoms: vec![beers, categories],
+    }
+}
+
+// cq7
+// a single atom: the breweries of one city, only a selection and a projection.
+pub fn create_cq7() -> ConjunctiveQuery {
+    // only in-- breweries --
+    let brew_id = &Term::Variable("brew_id");
+    let brew_name = &Term::Variable("brew_name");
+    let address1 = &Term::Variable("address1");
+    let address2 = &Term::Variable("address2");
+    let city = &Term::Constant("Bamberg");
+    let state = &Term::Variable("state");
+    let code = &Term::Variable("code");
+    let country = &Term::Variable("country");
+    let phone = &Term::Variable("phone");
+    let website = &Term::Variable("website");
+    let description = &Term::Variable("description");
+
+    let breweries = Atom {
+        name: "Breweries",
+        terms: vec![
+            brew_id,
+            brew_name,
+            address1,
+            address2,
+            city,
+            state,
+            code,
+            country,
+            phone,
+            website,
+            description,
+        ],
+    };
+
+    let answer = Atom {
+        name: "Answer",
+        terms: vec![brew_name, address1],
+    };
+
+    ConjunctiveQuery {
+        head_atom: answer,
+        body_atoms: vec![breweries],
+    }
+}
+
 pub fn create_example_query() -> ConjunctiveQuery {
     // Varibales
     let beer_id = &Term::Variable("beer_id");
@@ -419,3 +543,57 @@ pub fn create_cyclic_example_query() -> ConjunctiveQuery {
         body_atoms: vec![abc, bc, cd, bef, ce],
     }
 }
+
+// acyclic, but only the containment rule of the gyo reduction applies at first:
+// every variable is in two atoms, ab and bc are contained in abc.
+#[cfg(test)]
+pub fn create_containment_example_query() -> ConjunctiveQuery {
+    let a = &Term::Variable("a");
+    let b = &Term::Variable("b");
+    let c = &Term::Variable("c");
+
+    let containment_query = Atom {
+        name: "containment_query",
+        terms: vec![],
+    };
+    let ab = Atom {
+        name: "ab",
+        terms: vec![a, b],
+    };
+    let abc = Atom {
+        name: "abc",
+        terms: vec![a, b, c],
+    };
+    let bc = Atom {
+        name: "bc",
+        terms: vec![b, c],
+    };
+    ConjunctiveQuery {
+        head_atom: containment_query,
+        body_atoms: vec![ab, abc, bc],
+    }
+}
+
+// acyclic: two atoms over the same variables, one of them is removed as contained in the other.
+#[cfg(test)]
+pub fn create_equal_atoms_example_query() -> ConjunctiveQuery {
+    let a = &Term::Variable("a");
+    let b = &Term::Variable("b");
+
+    let equal_atoms_query = Atom {
+        name: "equal_atoms_query",
+        terms: vec![],
+    };
+    let r = Atom {
+        name: "r",
+        terms: vec![a, b],
+    };
+    let s = Atom {
+        name: "s",
+        terms: vec![b, a],
+    };
+    ConjunctiveQuery {
+        head_atom: equal_atoms_query,
+        body_atoms: vec![r, s],
+    }
+}
@@ -0,0 +1,205 @@
+// Worst-case optimal join (Generic Join) for cyclic queries:
+// instead of joining two relations at a time, the query is answered one variable at a time.
+// For every variable the values are the intersection of the values the atoms that contain
+// it still allow, so no intermediate result is larger than the AGM bound of the query.
+// Every atom is stored as a hashed trie over its variables in the global variable order.
+
+use std::collections::{HashMap, HashSet};
+use std::time::Instant;
+
+use arrow::{datatypes::Field, record_batch::RecordBatch};
+
+use crate::explain::{elapsed_us, GenericJoinReport};
+use crate::hypergraph::Hypergraph;
+use crate::join::{make_record_batch, value_at, values_to_array, NullPolicy, Value};
+use crate::queries::ConjunctiveQuery;
+
+// a hashed trie: every level is one variable of the atom.
+#[derive(Debug, Default)]
+struct Trie {
+    children: HashMap<Value, Trie>,
+}
+
+impl Trie {
+    fn insert(&mut self, values: Vec<Value>) {
+        let mut node = self;
+        for value in values {
+            node = node.children.entry(value).or_default();
+        }
+    }
+}
+
+// the order in which the variables (vertices of the hypergraph) are bound: first the variable
+// that appears in the most atoms, then always a variable connected to the ones already chosen
+// (most atoms first), ties are broken by the size of the smallest relation that contains it.
+pub fn variable_order(
+    hypergraph: &Hypergraph,
+    relations: &HashMap<String, RecordBatch>,
+) -> Vec<usize> {
+    let mut chosen = vec![false; hypergraph.vertices.len()];
+    let mut order: Vec<usize> = Vec::new();
+    while order.len() < hypergraph.vertices.len() {
+        let next = (0..hypergraph.vertices.len())
+            .filter(|&v| !chosen[v])
+            .max_by_key(|&v| {
+                let atoms = hypergraph.edges_of(v);
+                let connected = atoms
+                    .iter()
+                    .any(|&e| hypergraph.edges[e].vertices.iter().any(|&w| chosen[w]));
+                let smallest = atoms
+                    .iter()
+                    .map(|&e| relations[hypergraph.edges[e].name].num_rows())
+                    .min()
+                    .unwrap_or(0);
+                (connected, atoms.len(), usize::MAX - smallest)
+            })
+            .unwrap();
+        chosen[next] = true;
+        order.push(next);
+    }
+    order
+}
+
+// the state of the search: the values bound so far and the answers found.
+struct Search<'a> {
+    order: &'a [&'static str],
+    // for every variable in the order, the atoms (index) that contain it.
+    atoms_of: Vec<Vec<usize>>,
+    head: Vec<usize>,
+    binding: Vec<Value>,
+    answers: HashSet<Vec<Value>>,
+    boolean: bool,
+}
+
+impl Search<'_> {
+    // bind the variable at depth, nodes holds the current trie node of every atom.
+    fn run<'t>(&mut self, depth: usize, nodes: &mut Vec<&'t Trie>) {
+        if self.boolean && !self.answers.is_empty() {
+            return;
+        }
+        if depth == self.order.len() {
+            let answer = self.head.iter().map(|&i| self.binding[i].clone()).collect();
+            self.answers.insert(answer);
+            return;
+        }
+        let atoms = self.atoms_of[depth].clone();
+        // iterate over the smallest set of values and probe the others.
+        let smallest = *atoms
+            .iter()
+            .min_by_key(|&&a| nodes[a].children.len())
+            .unwrap();
+        let smallest: &'t Trie = nodes[smallest];
+        for value in smallest.children.keys() {
+            let children: Option<Vec<&'t Trie>> = atoms
+                .iter()
+                .map(|&a| nodes[a].children.get(value))
+                .collect();
+            let Some(children) = children else {
+                continue;
+            };
+            let saved: Vec<&'t Trie> = atoms.iter().map(|&a| nodes[a]).collect();
+            for (&a, child) in atoms.iter().zip(children) {
+                nodes[a] = child;
+            }
+            self.binding.push(value.clone());
+            self.run(depth + 1, nodes);
+            self.binding.pop();
+            for (&a, node) in atoms.iter().zip(saved) {
+                nodes[a] = node;
+            }
+        }
+    }
+}
+
+// answer the query with generic join over the relations bound to the atoms.
+// the result is projected on the head, a boolean query stops at the first answer.
+pub fn generic_join(
+    query: &ConjunctiveQuery,
+    relations: &HashMap<String, RecordBatch>,
+    null_policy: NullPolicy,
+) -> (RecordBatch, GenericJoinReport) {
+    let start = Instant::now();
+    let hypergraph = Hypergraph::from_query(query);
+    let vertices = variable_order(&hypergraph, relations);
+    let order: Vec<&'static str> = vertices.iter().map(|&v| hypergraph.vertices[v]).collect();
+    let atoms_of: Vec<Vec<usize>> = vertices
+        .iter()
+        .map(|&v| hypergraph.edges_of(v).to_vec())
+        .collect();
+
+    // build a trie for every atom, its levels follow the global variable order.
+    let tries: Vec<Trie> = hypergraph
+        .edges
+        .iter()
+        .map(|edge| {
+            let relation = &relations[edge.name];
+            let schema = relation.schema();
+            let levels: Vec<(usize, bool)> = vertices
+                .iter()
+                .enumerate()
+                .filter(|(_, v)| edge.vertices.contains(v))
+                .map(|(position, &v)| {
+                    let column = schema.index_of(hypergraph.vertices[v]).unwrap();
+                    (column, atoms_of[position].len() > 1)
+                })
+                .collect();
+            let mut trie = Trie::default();
+            for row in 0..relation.num_rows() {
+                let values: Vec<Value> = levels
+                    .iter()
+                    .map(|&(column, _)| value_at(relation.column(column), row))
+                    .collect();
+                // under sql semantics a NULL never joins.
+                let joins_null = levels
+                    .iter()
+                    .zip(&values)
+                    .any(|(&(_, joined), value)| joined && *value == Value::Null);
+                if null_policy == NullPolicy::Sql && joins_null {
+                    continue;
+                }
+                trie.insert(values);
+            }
+            trie
+        })
+        .collect();
+
+    let head_variables = query.head_atom.variables();
+    let mut search = Search {
+        order: &order,
+        atoms_of,
+        head: head_variables
+            .iter()
+            .map(|v| order.iter().position(|w| w == v).unwrap())
+            .collect(),
+        binding: Vec::new(),
+        answers: HashSet::new(),
+        boolean: head_variables.is_empty(),
+    };
+    let mut nodes: Vec<&Trie> = tries.iter().collect();
+    search.run(0, &mut nodes);
+
+    // make the arrow columns of the answers.
+    let mut answers: Vec<Vec<Value>> = search.answers.into_iter().collect();
+    answers.sort();
+    let mut fields = Vec::new();
+    let mut columns = Vec::new();
+    for (index, variable) in head_variables.iter().enumerate() {
+        let atom = hypergraph.edges_of(hypergraph.vertex(variable).unwrap())[0];
+        let schema = relations[hypergraph.edges[atom].name].schema();
+        let data_type = schema
+            .field_with_name(variable)
+            .unwrap()
+            .data_type()
+            .clone();
+        let values: Vec<Value> = answers.iter().map(|answer| answer[index].clone()).collect();
+        columns.push(values_to_array(&values, &data_type));
+        fields.push(Field::new(*variable, data_type, true));
+    }
+    let result = make_record_batch(fields, columns, answers.len());
+    let report = GenericJoinReport {
+        variable_order: order.iter().map(|v| v.to_string()).collect(),
+        output_rows: answers.len(),
+        elapsed_us: elapsed_us(start),
+    };
+    (result, report)
+}
@@ -1,56 +1,1045 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::time::Instant;
 
-use arrow::{compute::and, compute::filter_record_batch, record_batch::RecordBatch};
+use arrow::{
+    array::{new_empty_array, BooleanArray},
+    compute::and,
+    datatypes::{DataType, Field},
+    record_batch::RecordBatch,
+};
 
-use crate::csvout::write_record_batch_to_csv;
+use crate::bloom::BloomFilterPass;
+use crate::coercion::{coerce_join_keys, KeyCoercion};
+use crate::cost::Statistics;
+use crate::decomposition::{decompose, Decomposition};
+use crate::enumeration::Enumeration;
+use crate::explain::{elapsed_us, BagReport, Explain, JoinReport, SelectionReport};
+use crate::gyo::{acyclic_join_forest, free_connex};
+use crate::hypergraph::Hypergraph;
+use crate::join::{distinct, hash_join, make_record_batch, project, select_rows, NullPolicy};
 use crate::jointrees::{
-    join_tree, make_boolean_array_float64, make_boolean_array_string, make_boolean_array_string,
-    reduce,
+    build_join_forest, join_tree, make_boolean_array_columns, make_boolean_array_constant, reduce,
+    semi_join_program, validate_join_forest, Direction, JoinTreeNode, Selections,
 };
-use crate::queries::ConjunctiveQuery;
+use crate::queries::{Atom, ConjunctiveQuery, Term};
+use crate::wcoj::generic_join;
+
+// the loaded relations by name. evaluation never changes the database: every query
+// works on its own views of the relations, which share the arrow buffers with it.
+// so one database can serve many queries, one after the other or concurrently.
+pub type Database = HashMap<String, RecordBatch>;
+
+// how a query is evaluated when it is cyclic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CyclicStrategy {
+    // run the semijoin reducer and the join phase on the bags of a decomposition.
+    #[default]
+    Decomposition,
+    // worst-case optimal generic join over the atoms.
+    GenericJoin,
+}
+
+// options for the evaluation of a query.
+#[derive(Debug, Clone, Copy)]
+pub struct Options {
+    pub null_policy: NullPolicy,
+    pub key_coercion: KeyCoercion,
+    pub cyclic_strategy: CyclicStrategy,
+    // the threads of the semijoin reducer, 1 runs the steps one after the other.
+    pub threads: usize,
+    // a bloom filter pre-pass before the semijoins with a large source.
+    // none runs the exact semijoins only.
+    pub bloom_filter: Option<BloomFilterPass>,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            null_policy: NullPolicy::default(),
+            key_coercion: KeyCoercion::default(),
+            cyclic_strategy: CyclicStrategy::default(),
+            threads: 1,
+            bloom_filter: None,
+        }
+    }
+}
+
+// the answer to a query: boolean queries (empty head) are answered with true or false,
+// the other queries with the relation of their answers.
+#[derive(Debug)]
+pub enum Answer {
+    Boolean(bool),
+    Relation(RecordBatch),
+}
+
+// bind a relation to the terms of an atom: the columns of its first variables, named after
+// their variable. the rows that pass the selections of the atom (constants and variables that
+// appear more than once) go to its selection vector, when not every row does. the rows are not
+// copied here, the reducer starts from the selected rows and materialize copies them once.
+fn bind_atom(
+    atom: &Atom,
+    relation: &RecordBatch,
+    options: Options,
+    selections: &mut Selections,
+    explain: &mut Explain,
+) -> Result<RecordBatch, Box<dyn Error>> {
+    let start = Instant::now();
+    let schema = relation.schema();
+    let mut predicates = Vec::new();
+    let mut predicate = BooleanArray::from(vec![true; relation.num_rows()]);
+    let mut first_column: HashMap<&str, usize> = HashMap::new();
+    let mut fields = Vec::new();
+    let mut columns = Vec::new();
 
-pub fn perform_query(
+    for (index, term) in atom.terms.iter().enumerate() {
+        match term {
+            Term::Constant(value) => {
+                let selection = make_boolean_array_constant(relation, index, value);
+                predicate = and(&predicate, &selection).unwrap();
+                predicates.push(format!("{} = {}", schema.field(index).name(), term));
+            }
+            Term::Variable(name) => match first_column.get(name) {
+                Some(&first) => {
+                    let selection = make_boolean_array_columns(
+                        relation,
+                        first,
+                        index,
+                        options.null_policy,
+                        options.key_coercion,
+                    )?;
+                    predicate = and(&predicate, &selection).unwrap();
+                    predicates.push(format!(
+                        "{} = {}",
+                        schema.field(first).name(),
+                        schema.field(index).name()
+                    ));
+                }
+                None => {
+                    first_column.insert(name, index);
+                    let column = relation.column(index);
+                    fields.push(Field::new(*name, column.data_type().clone(), true));
+                    columns.push(column.clone());
+                }
+            },
+        }
+    }
+    let bound = make_record_batch(fields, columns, relation.num_rows());
+    // the view shares all columns with the relation.
+    let mut output_rows = relation.num_rows();
+    if predicate.true_count() < relation.num_rows() {
+        let rows: Vec<u32> = (0..predicate.len() as u32)
+            .filter(|&row| predicate.value(row as usize))
+            .collect();
+        output_rows = rows.len();
+        selections.insert(atom.name.to_string(), rows);
+    }
+    explain.selections.push(SelectionReport {
+        relation: atom.name.to_string(),
+        predicates,
+        input_rows: relation.num_rows(),
+        output_rows,
+        elapsed_us: elapsed_us(start),
+    });
+    Ok(bound)
+}
+
+// make the views of a query: bind the relations to the variables of their atom, the rows that
+// pass the selections of an atom are its first selection vector.
+fn bind_relations(
     query: &ConjunctiveQuery,
-    data: &mut HashMap<String, RecordBatch>,
-) -> RecordBatch {
-    // example for cq4, need to automate this part.
-    let atom = query.body_atoms[0].clone();
-    let rb = data.get(atom.name).unwrap();
+    data: &Database,
+    options: Options,
+    explain: &mut Explain,
+) -> Result<(HashMap<String, RecordBatch>, Selections), Box<dyn Error>> {
+    let mut relations: HashMap<String, RecordBatch> = HashMap::new();
+    let mut selections = Selections::new();
+    for atom in &query.body_atoms {
+        let relation = data
+            .get(atom.name)
+            .ok_or(format!("No relation found for atom: {}", atom.name))?;
+        let bound = bind_atom(atom, relation, options, &mut selections, explain)?;
+        relations.insert(atom.name.to_string(), bound);
+    }
+    // give the join variables the same type in every relation
+    coerce_join_keys(&mut relations, options.key_coercion)?;
+    Ok((relations, selections))
+}
 
-    // Create array(s)
-    let column = rb.schema().index_of("style").unwrap_or(42);
-    let column2 = rb.schema().index_of("abv").unwrap_or(42);
-    let column3 = rb.schema().index_of("ibu").unwrap_or(42);
+// true when every relation has a selected row.
+fn all_non_empty(relations: &HashMap<String, RecordBatch>, selections: &Selections) -> bool {
+    relations.iter().all(|(name, relation)| {
+        selections
+            .get(name)
+            .map_or(relation.num_rows(), |rows| rows.len())
+            > 0
+    })
+}
 
-    // get constants from terms in atom
-    let value = "Vienna Lager";
-    let ba = make_boolean_array_string(rb, column, value);
+// a relation the reducer and the join phase run on: the view of an atom, or for a cyclic
+// query a bag of its decomposition. its relation has one column per variable.
+struct Node {
+    name: String,
+    variables: Vec<String>,
+}
 
-    let value2 = 0.05;
-    let ba2 = make_boolean_array_float64(rb, column2, value2);
+// the relation of every bag: the join of the atoms that cover the bag and of the atoms
+// that are enforced in it, each projected on the variables of the bag first.
+fn materialize_bags(
+    query: &ConjunctiveQuery,
+    decomposition: &Decomposition,
+    relations: &HashMap<String, RecordBatch>,
+    null_policy: NullPolicy,
+    explain: &mut Explain,
+) -> HashMap<String, RecordBatch> {
+    let mut bags = HashMap::new();
+    for bag in &decomposition.bags {
+        let start = Instant::now();
+        let variables: Vec<String> = bag.variables.iter().map(|v| v.to_string()).collect();
+        let mut atoms = bag.cover.clone();
+        atoms.extend(bag.atoms.iter().filter(|a| !bag.cover.contains(a)));
 
-    let value3 = "18";
-    let ba3 = make_boolean_array_string(rb, column3, value3);
+        let mut result = make_record_batch(Vec::new(), Vec::new(), 1);
+        for &a in &atoms {
+            let atom = &query.body_atoms[a];
+            let keep: Vec<String> = atom
+                .variables()
+                .iter()
+                .filter(|v| bag.variables.contains(v))
+                .map(|v| v.to_string())
+                .collect();
+            let part = distinct(&project(&relations[atom.name], &keep));
+            result = hash_join(&result, &part, null_policy);
+        }
+        let result = distinct(&project(&result, &variables));
+        explain.bags.push(BagReport {
+            bag: bag.name.clone(),
+            variables: variables.clone(),
+            atoms: atoms
+                .iter()
+                .map(|&a| query.body_atoms[a].name.to_string())
+                .collect(),
+            output_rows: result.num_rows(),
+            elapsed_us: elapsed_us(start),
+        });
+        bags.insert(bag.name.clone(), result);
+    }
+    bags
+}
+
+// the reduced relations: the rows kept by the reducer of the columns of the needed variables.
+// the rows are copied once, here, and only the needed columns are copied.
+fn materialize(
+    relations: &HashMap<String, RecordBatch>,
+    selections: &Selections,
+    needed: impl Fn(&str) -> bool,
+) -> HashMap<String, RecordBatch> {
+    relations
+        .iter()
+        .map(|(name, relation)| {
+            let keep: Vec<String> = relation
+                .schema()
+                .fields()
+                .iter()
+                .map(|field| field.name().clone())
+                .filter(|variable| needed(variable))
+                .collect();
+            let projected = project(relation, &keep);
+            let reduced = match selections.get(name) {
+                Some(rows) => select_rows(&projected, rows),
+                None => projected,
+            };
+            (name.clone(), reduced)
+        })
+        .collect()
+}
+
+// the nodes in the connected components of the query hypergraph, in order of their first node.
+// the variables of a node are variables of the query, so its first variable gives its
+// component; a node without variables is a component on its own.
+// the nodes of a component are in join order: every node shares a variable with one before it.
+fn components<'n>(query: &ConjunctiveQuery, nodes: &'n [Node]) -> Vec<Vec<&'n Node>> {
+    let hypergraph = Hypergraph::from_query(query);
+    let mut component_of = vec![0; hypergraph.vertices.len()];
+    for (component, edges) in hypergraph.components().iter().enumerate() {
+        for &e in edges {
+            for &v in &hypergraph.edges[e].vertices {
+                component_of[v] = component;
+            }
+        }
+    }
+    let mut components: Vec<(Option<usize>, Vec<&Node>)> = Vec::new();
+    for node in nodes {
+        let component = node
+            .variables
+            .first()
+            .and_then(|variable| hypergraph.vertex(variable))
+            .map(|v| component_of[v]);
+        match components
+            .iter_mut()
+            .find(|(other, _)| component.is_some() && *other == component)
+        {
+            Some((_, members)) => members.push(node),
+            None => components.push((component, vec![node])),
+        }
+    }
+    components
+        .into_iter()
+        .map(|(_, mut remaining)| {
+            let mut order: Vec<&Node> = vec![remaining.remove(0)];
+            let mut joined: HashSet<&String> = order[0].variables.iter().collect();
+            while !remaining.is_empty() {
+                let next = remaining
+                    .iter()
+                    .position(|node| node.variables.iter().any(|v| joined.contains(v)))
+                    .unwrap_or(0);
+                let node = remaining.remove(next);
+                joined.extend(&node.variables);
+                order.push(node);
+            }
+            order
+        })
+        .collect()
+}
+
+// join the relations of a component and project on its head variables.
+fn join_component(
+    order: &[&Node],
+    head: &[String],
+    relations: &HashMap<String, RecordBatch>,
+    null_policy: NullPolicy,
+    explain: &mut Explain,
+) -> RecordBatch {
+    let mut result = relations[&order[0].name].clone();
+    for (position, node) in order.iter().enumerate().skip(1) {
+        let start = Instant::now();
+        let left_rows = result.num_rows();
+        result = hash_join(&result, &relations[&node.name], null_policy);
+        explain.joins.push(JoinReport {
+            relation: node.name.clone(),
+            left_rows,
+            right_rows: relations[&node.name].num_rows(),
+            output_rows: result.num_rows(),
+            cross_product: false,
+            elapsed_us: elapsed_us(start),
+        });
+        // only keep the head variables and the variables still needed for the next joins.
+        let needed: HashSet<&String> = order[position + 1..]
+            .iter()
+            .flat_map(|node| &node.variables)
+            .chain(head)
+            .collect();
+        let keep: Vec<String> = result
+            .schema()
+            .fields()
+            .iter()
+            .map(|field| field.name().clone())
+            .filter(|name| needed.contains(name))
+            .collect();
+        result = distinct(&project(&result, &keep));
+    }
+    let keep: Vec<String> = head
+        .iter()
+        .filter(|v| order.iter().any(|node| node.variables.contains(v)))
+        .cloned()
+        .collect();
+    distinct(&project(&result, &keep))
+}
+
+// perform the query on the reduced database: join the relations and project on the head.
+// a component is joined on its own, the components share no variables and their answers
+// are combined with a cross product.
+fn perform_query(
+    query: &ConjunctiveQuery,
+    nodes: &[Node],
+    head: &[String],
+    relations: &HashMap<String, RecordBatch>,
+    null_policy: NullPolicy,
+    explain: &mut Explain,
+) -> RecordBatch {
+    let mut result = make_record_batch(Vec::new(), Vec::new(), 1);
+    for (index, order) in components(query, nodes).iter().enumerate() {
+        let component = join_component(order, head, relations, null_policy, explain);
+        if index == 0 {
+            result = component;
+            continue;
+        }
+        let start = Instant::now();
+        let left_rows = result.num_rows();
+        result = hash_join(&result, &component, null_policy);
+        explain.joins.push(JoinReport {
+            relation: order[0].name.clone(),
+            left_rows,
+            right_rows: component.num_rows(),
+            output_rows: result.num_rows(),
+            cross_product: true,
+            elapsed_us: elapsed_us(start),
+        });
+    }
+    project(&result, head)
+}
+
+// the answer without rows: a column per head variable, of its type in the relations.
+fn empty_answer(head: &[String], relations: &HashMap<String, RecordBatch>) -> RecordBatch {
+    let fields: Vec<Field> = head
+        .iter()
+        .map(|variable| {
+            let data_type = relations
+                .values()
+                .find_map(|relation| {
+                    let schema = relation.schema();
+                    let field = schema.field_with_name(variable).ok()?;
+                    Some(field.data_type().clone())
+                })
+                .unwrap_or(DataType::Null);
+            Field::new(variable, data_type, true)
+        })
+        .collect();
+    let columns = fields
+        .iter()
+        .map(|field| new_empty_array(field.data_type()))
+        .collect();
+    make_record_batch(fields, columns, 0)
+}
+
+// the statistics of the views of a query, the relations after the selections.
+pub fn statistics(
+    query: &ConjunctiveQuery,
+    data: &Database,
+    options: Options,
+) -> Result<Statistics, Box<dyn Error>> {
+    let (relations, selections) = bind_relations(query, data, options, &mut Explain::default())?;
+    Ok(Statistics::new(query, &relations, &selections))
+}
 
-    // combine the boolean arrays
-    let combined_ba = and(&and(&ba, &ba2).unwrap(), &ba3).unwrap();
+pub fn yannakaki(
+    query: &ConjunctiveQuery,
+    data: &Database,
+    options: Options,
+) -> Result<Answer, Box<dyn Error>> {
+    yannakaki_explain(query, data, options).map(|(answer, _)| answer)
+}
 
-    // Filter the record batch based on the predicate
-    let filtered_record_batch = filter_record_batch(rb, &combined_ba).unwrap();
+// run yannakaki and explain what happened: the join tree, the selections,
+// every semijoin step and every join with their row counts and elapsed time.
+pub fn yannakaki_explain(
+    query: &ConjunctiveQuery,
+    data: &Database,
+    options: Options,
+) -> Result<(Answer, Explain), Box<dyn Error>> {
+    evaluate(query, data, options, None)
+}
 
-    filtered_record_batch
+// run yannakaki on a given join forest of an acyclic query,
+// e.g. on another of its join trees (see all_join_forests).
+pub fn yannakaki_join_forest(
+    query: &ConjunctiveQuery,
+    data: &Database,
+    options: Options,
+    forest: &[JoinTreeNode],
+) -> Result<Answer, Box<dyn Error>> {
+    if acyclic_join_forest(query).is_none() {
+        return Err(format!("Query is not acyclic: {}", query).into());
+    }
+    validate_join_forest(query, forest)?;
+    evaluate(query, data, options, Some(forest)).map(|(answer, _)| answer)
 }
 
-pub fn yannakaki(query: &ConjunctiveQuery, data: &mut HashMap<String, RecordBatch>) {
-    let mut semi_join_info = join_tree(&query.body_atoms);
-    // forward phase reducer
-    reduce(semi_join_info.clone(), data);
-    // backwardward phase reducer
-    semi_join_info.reverse();
-    reduce(semi_join_info, data);
+// evaluate a query on a join forest, or on its cheapest join forest without one.
+fn evaluate(
+    query: &ConjunctiveQuery,
+    data: &Database,
+    options: Options,
+    forest: Option<&[JoinTreeNode]>,
+) -> Result<(Answer, Explain), Box<dyn Error>> {
+    let start = Instant::now();
+    let mut explain = Explain {
+        query: query.to_string(),
+        bloom_filter: options.bloom_filter,
+        ..Default::default()
+    };
+    let (mut relations, mut selections) = bind_relations(query, data, options, &mut explain)?;
+
+    let boolean = query.head_atom.terms.is_empty();
+    let head: Vec<String> = query
+        .head_atom
+        .variables()
+        .iter()
+        .map(|name| name.to_string())
+        .collect();
+    // a relation without tuples after the selections: there are no answers.
+    let mut non_empty = all_non_empty(&relations, &selections);
+
+    let acyclic = acyclic_join_forest(query).is_some();
+    if !acyclic && options.cyclic_strategy == CyclicStrategy::GenericJoin {
+        // generic join only sees the atoms through their variables,
+        // an atom with constants only and no rows would be missed.
+        if !non_empty {
+            explain.elapsed_us = elapsed_us(start);
+            let answer = match boolean {
+                true => Answer::Boolean(false),
+                false => Answer::Relation(empty_answer(&head, &relations)),
+            };
+            return Ok((answer, explain));
+        }
+        let relations = materialize(&relations, &selections, |_| true);
+        let (result, report) = generic_join(query, &relations, options.null_policy);
+        explain.generic_join = Some(report);
+        explain.answer_rows = result.num_rows();
+        explain.elapsed_us = elapsed_us(start);
+        let answer = match boolean {
+            true => Answer::Boolean(result.num_rows() > 0),
+            false => Answer::Relation(result),
+        };
+        return Ok((answer, explain));
+    }
+
+    // an acyclic query runs on its join tree, a cyclic query on the bags of a decomposition.
+    let (program, nodes): (_, Vec<Node>) = if acyclic {
+        let forest = match forest {
+            Some(forest) => forest.to_vec(),
+            None => build_join_forest(query, &Statistics::new(query, &relations, &selections)),
+        };
+        debug_assert_eq!(validate_join_forest(query, &forest), Ok(()));
+        let program = semi_join_program(query, &forest);
+        // the join phase follows the join trees: the roots, then every atom after its parent.
+        let top_down = program.pass(Direction::TopDown);
+        let nodes = program
+            .roots
+            .iter()
+            .chain(top_down.iter().map(|step| &step.target))
+            .map(|name| {
+                let atom = query
+                    .body_atoms
+                    .iter()
+                    .find(|atom| atom.name == *name)
+                    .unwrap();
+                Node {
+                    name: name.clone(),
+                    variables: atom.variables().iter().map(|v| v.to_string()).collect(),
+                }
+            })
+            .collect();
+        (program, nodes)
+    } else {
+        let decomposition = decompose(query);
+        explain.width = Some(decomposition.width());
+        // the bags are new relations of the selected rows of the atoms.
+        relations = materialize_bags(
+            query,
+            &decomposition,
+            &materialize(&relations, &selections, |_| true),
+            options.null_policy,
+            &mut explain,
+        );
+        selections = Selections::new();
+        non_empty = non_empty && all_non_empty(&relations, &selections);
+        let nodes = decomposition
+            .bags
+            .iter()
+            .map(|bag| Node {
+                name: bag.name.clone(),
+                variables: bag.variables.iter().map(|v| v.to_string()).collect(),
+            })
+            .collect();
+        (decomposition.semi_join_program(), nodes)
+    };
+    explain.program = program.clone();
+    // the reducer: a boolean query is answered after the forward phase, the root of every
+    // component is reduced by the other relations of its component, so every component is
+    // answered on its own and the query is true if no relation became empty. the other
+    // queries run the backward phase too.
+    let steps = match boolean {
+        true => program.pass(Direction::BottomUp),
+        false => program.steps.clone(),
+    };
+    if non_empty {
+        non_empty = reduce(
+            &steps,
+            &relations,
+            &mut selections,
+            options.null_policy,
+            options.threads,
+            options.bloom_filter,
+            &mut explain.semijoins,
+        );
+    }
+    if boolean {
+        explain.answer_rows = usize::from(non_empty);
+        explain.elapsed_us = elapsed_us(start);
+        return Ok((Answer::Boolean(non_empty), explain));
+    }
+    // a relation became empty: there are no answers, and the relations that were only partly
+    // reduced are not joined.
+    if !non_empty {
+        explain.elapsed_us = elapsed_us(start);
+        return Ok((Answer::Relation(empty_answer(&head, &relations)), explain));
+    }
+    // only the head variables and the variables the nodes join on are needed from here.
+    let relations = materialize(&relations, &selections, |variable| {
+        head.iter().any(|v| v == variable)
+            || nodes
+                .iter()
+                .filter(|node| node.variables.iter().any(|v| v == variable))
+                .count()
+                > 1
+    });
 
     // perform query on reduced database
-    let result = perform_query(query, data);
-    // write to csv
-    write_record_batch_to_csv(&result, "output");
+    let result = perform_query(
+        query,
+        &nodes,
+        &head,
+        &relations,
+        options.null_policy,
+        &mut explain,
+    );
+    explain.answer_rows = result.num_rows();
+    explain.elapsed_us = elapsed_us(start);
+    Ok((Answer::Relation(result), explain))
+}
+
+// evaluate a free-connex query up to its answers: the selections and both semijoin passes run
+// first (linear time), then the answers are enumerated one by one with constant delay.
+pub fn yannakaki_enumerate(
+    query: &ConjunctiveQuery,
+    data: &Database,
+    options: Options,
+) -> Result<Enumeration, Box<dyn Error>> {
+    if !free_connex(query) {
+        return Err(format!("Query is not free-connex: {}", query).into());
+    }
+    let mut explain = Explain::default();
+    let (relations, mut selections) = bind_relations(query, data, options, &mut explain)?;
+    let program = join_tree(query, &Statistics::new(query, &relations, &selections));
+    if all_non_empty(&relations, &selections) {
+        reduce(
+            &program.steps,
+            &relations,
+            &mut selections,
+            options.null_policy,
+            options.threads,
+            options.bloom_filter,
+            &mut explain.semijoins,
+        );
+    }
+    // the answers only need the head variables.
+    let head = query.head_atom.variables();
+    let relations = materialize(&relations, &selections, |variable| head.contains(&variable));
+    Ok(Enumeration::new(query, &relations, options.null_policy))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::join::{value_at, Value};
+    use crate::jointrees::all_join_forests;
+    use crate::loader::{load_relation, needed_columns};
+    use crate::queries::{create_cq6, create_cq7};
+    use arrow::array::{ArrayRef, Int64Array};
+    use std::sync::Arc;
+
+    // a relation of Int64 columns from its rows.
+    fn relation(columns: &[&str], rows: &[&[i64]]) -> RecordBatch {
+        let rows: Vec<Vec<Option<i64>>> = rows
+            .iter()
+            .map(|row| row.iter().map(|&value| Some(value)).collect())
+            .collect();
+        nullable_relation(columns, &rows)
+    }
+
+    // a relation of Int64 columns from its rows, None is NULL.
+    fn nullable_relation(columns: &[&str], rows: &[Vec<Option<i64>>]) -> RecordBatch {
+        let fields = columns
+            .iter()
+            .map(|&column| Field::new(column, DataType::Int64, true))
+            .collect();
+        let arrays = (0..columns.len())
+            .map(|c| {
+                let values: Int64Array = rows.iter().map(|row| row[c]).collect();
+                Arc::new(values) as ArrayRef
+            })
+            .collect();
+        make_record_batch(fields, arrays, rows.len())
+    }
+
+    // the rows of an answer, sorted.
+    fn rows(answer: Answer) -> Vec<Vec<Value>> {
+        let Answer::Relation(result) = answer else {
+            panic!("not a relation");
+        };
+        let mut rows: Vec<Vec<Value>> = (0..result.num_rows())
+            .map(|row| {
+                result
+                    .columns()
+                    .iter()
+                    .map(|column| value_at(column, row))
+                    .collect()
+            })
+            .collect();
+        rows.sort();
+        rows
+    }
+
+    #[test]
+    fn every_join_forest_gives_the_same_answers() {
+        let variable = |name| &*Box::leak(Box::new(Term::Variable(name)));
+        let (a, b, c, d, e) = (
+            variable("a"),
+            variable("b"),
+            variable("c"),
+            variable("d"),
+            variable("e"),
+        );
+        let atom = |name, terms| Atom { name, terms };
+        let query = ConjunctiveQuery {
+            head_atom: atom("Answer", vec![b, c, e]),
+            body_atoms: vec![
+                atom("R", vec![a, b]),
+                atom("S", vec![a, c]),
+                atom("T", vec![a, d]),
+                atom("U", vec![d, e]),
+            ],
+        };
+        let mut data = Database::new();
+        data.insert(
+            "R".to_string(),
+            relation(&["a", "b"], &[&[1, 10], &[1, 11], &[2, 12], &[3, 13]]),
+        );
+        data.insert(
+            "S".to_string(),
+            relation(&["a", "c"], &[&[1, 20], &[2, 21], &[4, 22]]),
+        );
+        data.insert(
+            "T".to_string(),
+            relation(&["a", "d"], &[&[1, 30], &[2, 31], &[2, 32], &[5, 33]]),
+        );
+        data.insert(
+            "U".to_string(),
+            relation(&["d", "e"], &[&[30, 40], &[32, 41], &[33, 42]]),
+        );
+        let options = Options::default();
+
+        let expected = rows(yannakaki(&query, &data, options).unwrap());
+        assert_eq!(expected.len(), 3);
+        let forests = all_join_forests(&query, &statistics(&query, &data, options).unwrap());
+        assert!(forests.len() > 1);
+        for (forest, _) in &forests {
+            let answer = yannakaki_join_forest(&query, &data, options, forest).unwrap();
+            assert_eq!(rows(answer), expected);
+        }
+    }
+
+    // a query over atoms of variables.
+    fn query(head: &[&'static str], atoms: &[(&'static str, &[&'static str])]) -> ConjunctiveQuery {
+        let atom = |name, variables: &[&'static str]| Atom {
+            name,
+            terms: variables
+                .iter()
+                .map(|&v| &*Box::leak(Box::new(Term::Variable(v))))
+                .collect(),
+        };
+        ConjunctiveQuery {
+            head_atom: atom("Answer", head),
+            body_atoms: atoms
+                .iter()
+                .map(|&(name, variables)| atom(name, variables))
+                .collect(),
+        }
+    }
+
+    fn int_rows(rows: &[&[i64]]) -> Vec<Vec<Value>> {
+        rows.iter()
+            .map(|row| row.iter().map(|&value| Value::Int64(value)).collect())
+            .collect()
+    }
+
+    fn triangle(head: &[&'static str]) -> (ConjunctiveQuery, Database) {
+        let query = query(
+            head,
+            &[("R", &["a", "b"]), ("S", &["b", "c"]), ("T", &["c", "a"])],
+        );
+        let mut data = Database::new();
+        data.insert(
+            "R".to_string(),
+            relation(&["a", "b"], &[&[1, 2], &[1, 3], &[2, 3], &[4, 5]]),
+        );
+        data.insert(
+            "S".to_string(),
+            relation(&["b", "c"], &[&[2, 3], &[3, 1], &[3, 4], &[5, 6]]),
+        );
+        data.insert(
+            "T".to_string(),
+            relation(&["c", "a"], &[&[3, 1], &[1, 1], &[4, 2], &[6, 9]]),
+        );
+        (query, data)
+    }
+
+    // abc, bc, cd, bef, ce: the cycle b, c, e through abc, bef and ce.
+    fn cyclic_example(head: &[&'static str]) -> (ConjunctiveQuery, Database) {
+        let query = query(
+            head,
+            &[
+                ("abc", &["a", "b", "c"]),
+                ("bc", &["b", "c"]),
+                ("cd", &["c", "d"]),
+                ("bef", &["b", "e", "f"]),
+                ("ce", &["c", "e"]),
+            ],
+        );
+        let mut data = Database::new();
+        data.insert(
+            "abc".to_string(),
+            relation(
+                &["a", "b", "c"],
+                &[&[1, 2, 3], &[1, 2, 4], &[2, 3, 3], &[5, 5, 5]],
+            ),
+        );
+        data.insert(
+            "bc".to_string(),
+            relation(&["b", "c"], &[&[2, 3], &[2, 4], &[3, 3]]),
+        );
+        data.insert(
+            "cd".to_string(),
+            relation(&["c", "d"], &[&[3, 10], &[4, 11], &[3, 12]]),
+        );
+        data.insert(
+            "bef".to_string(),
+            relation(&["b", "e", "f"], &[&[2, 7, 20], &[3, 8, 21], &[2, 9, 22]]),
+        );
+        data.insert(
+            "ce".to_string(),
+            relation(&["c", "e"], &[&[3, 7], &[4, 9], &[3, 8]]),
+        );
+        (query, data)
+    }
+
+    fn decomposition() -> Options {
+        Options {
+            cyclic_strategy: CyclicStrategy::Decomposition,
+            ..Options::default()
+        }
+    }
+
+    #[test]
+    fn decomposition_answers_a_triangle() {
+        let (query, data) = triangle(&["a", "b", "c"]);
+        let (answer, explain) = yannakaki_explain(&query, &data, decomposition()).unwrap();
+        assert!(explain.width.is_some());
+        assert_eq!(
+            rows(answer),
+            int_rows(&[&[1, 2, 3], &[1, 3, 1], &[2, 3, 4]])
+        );
+    }
+
+    #[test]
+    fn decomposition_answers_the_cyclic_example() {
+        let (query, data) = cyclic_example(&["a", "b", "c", "d", "e", "f"]);
+        let (answer, explain) = yannakaki_explain(&query, &data, decomposition()).unwrap();
+        assert!(explain.width.is_some());
+        assert_eq!(
+            rows(answer),
+            int_rows(&[
+                &[1, 2, 3, 10, 7, 20],
+                &[1, 2, 3, 12, 7, 20],
+                &[1, 2, 4, 11, 9, 22],
+                &[2, 3, 3, 10, 8, 21],
+                &[2, 3, 3, 12, 8, 21],
+            ])
+        );
+        // projected on some variables, without duplicates.
+        let (query, data) = cyclic_example(&["a", "f"]);
+        let answer = yannakaki(&query, &data, decomposition()).unwrap();
+        assert_eq!(rows(answer), int_rows(&[&[1, 20], &[1, 22], &[2, 21]]));
+        let (query, data) = cyclic_example(&[]);
+        let answer = yannakaki(&query, &data, decomposition()).unwrap();
+        assert!(matches!(answer, Answer::Boolean(true)));
+    }
+
+    fn generic_join() -> Options {
+        Options {
+            cyclic_strategy: CyclicStrategy::GenericJoin,
+            ..Options::default()
+        }
+    }
+
+    // the same answers from both strategies for cyclic queries.
+    fn assert_same_answers(query: &ConjunctiveQuery, data: &Database, options: Options) {
+        let decomposition = Options {
+            cyclic_strategy: CyclicStrategy::Decomposition,
+            ..options
+        };
+        let generic_join = Options {
+            cyclic_strategy: CyclicStrategy::GenericJoin,
+            ..options
+        };
+        let (expected, _) = yannakaki_explain(query, data, decomposition).unwrap();
+        let (answer, explain) = yannakaki_explain(query, data, generic_join).unwrap();
+        assert!(explain.generic_join.is_some());
+        match (answer, expected) {
+            (Answer::Boolean(answer), Answer::Boolean(expected)) => assert_eq!(answer, expected),
+            (answer, expected) => assert_eq!(rows(answer), rows(expected)),
+        }
+    }
+
+    #[test]
+    fn generic_join_gives_the_answers_of_the_decomposition() {
+        let heads: [&[&'static str]; 4] = [&["a", "b", "c"], &["a"], &["c", "a"], &[]];
+        for head in heads {
+            let (query, data) = triangle(head);
+            assert_same_answers(&query, &data, generic_join());
+        }
+        let heads: [&[&'static str]; 4] =
+            [&["a", "b", "c", "d", "e", "f"], &["a"], &["a", "f"], &[]];
+        for head in heads {
+            let (query, data) = cyclic_example(head);
+            assert_same_answers(&query, &data, generic_join());
+        }
+        let (query, data) = triangle(&["a", "b", "c"]);
+        let answer = yannakaki(&query, &data, generic_join()).unwrap();
+        assert_eq!(
+            rows(answer),
+            int_rows(&[&[1, 2, 3], &[1, 3, 1], &[2, 3, 4]])
+        );
+    }
+
+    #[test]
+    fn generic_join_and_decomposition_agree_on_null_keys() {
+        let (query, mut data) = triangle(&["a", "b", "c"]);
+        // a triangle through a NULL value of a: (NULL, 2, 3).
+        let r = vec![vec![Some(1), Some(2)], vec![None, Some(2)]];
+        let t = vec![vec![Some(3), Some(1)], vec![Some(3), None]];
+        data.insert("R".to_string(), nullable_relation(&["a", "b"], &r));
+        data.insert("T".to_string(), nullable_relation(&["c", "a"], &t));
+        for (null_policy, expected) in [
+            (NullPolicy::Sql, vec![vec![Some(1), Some(2), Some(3)]]),
+            (
+                NullPolicy::NullsEqual,
+                vec![
+                    vec![None, Some(2), Some(3)],
+                    vec![Some(1), Some(2), Some(3)],
+                ],
+            ),
+        ] {
+            let options = Options {
+                null_policy,
+                ..Options::default()
+            };
+            assert_same_answers(&query, &data, options);
+            let answer = yannakaki(&query, &data, options).unwrap();
+            let expected: Vec<Vec<Value>> = expected
+                .iter()
+                .map(|row| {
+                    row.iter()
+                        .map(|value| value.map_or(Value::Null, Value::Int64))
+                        .collect()
+                })
+                .collect();
+            assert_eq!(rows(answer), expected);
+        }
+    }
+
+    #[test]
+    fn enumeration_gives_the_answers_of_yannakaki() {
+        // free-connex: the head variables a, b, c are a subtree of the join tree.
+        let query = query(
+            &["a", "b", "c"],
+            &[("R", &["a", "b"]), ("S", &["b", "c"]), ("T", &["c", "d"])],
+        );
+        let mut data = Database::new();
+        data.insert(
+            "R".to_string(),
+            relation(&["a", "b"], &[&[1, 10], &[2, 10], &[3, 11], &[4, 12]]),
+        );
+        data.insert(
+            "S".to_string(),
+            relation(&["b", "c"], &[&[10, 20], &[10, 21], &[11, 22], &[13, 23]]),
+        );
+        data.insert(
+            "T".to_string(),
+            relation(&["c", "d"], &[&[20, 30], &[20, 31], &[21, 32], &[22, 33]]),
+        );
+        let options = Options::default();
+        let expected = rows(yannakaki(&query, &data, options).unwrap());
+        assert_eq!(expected.len(), 5);
+        let mut answers: Vec<Vec<Value>> = yannakaki_enumerate(&query, &data, options)
+            .unwrap()
+            .collect();
+        answers.sort();
+        assert_eq!(answers, expected);
+    }
+
+    #[test]
+    fn enumeration_rejects_a_query_that_is_not_free_connex() {
+        // the join variable brew_id is not in the head.
+        let query = crate::queries::create_cq4();
+        let Err(error) = yannakaki_enumerate(&query, &Database::new(), Options::default()) else {
+            panic!("cq4 is not free-connex");
+        };
+        assert!(error.to_string().starts_with("Query is not free-connex"));
+    }
+
+    // the relations of a query from the csv files in data, as main loads them.
+    fn load(query: &ConjunctiveQuery) -> Database {
+        needed_columns(&[query])
+            .into_iter()
+            .map(|(name, columns)| {
+                let file_path = format!("./data/{}.csv", name.to_lowercase());
+                (name, load_relation(&file_path, &columns).unwrap())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn disconnected_query_is_the_product_of_its_components() {
+        let query = create_cq6();
+        let data = load(&query);
+        let (answer, explain) = yannakaki_explain(&query, &data, Options::default()).unwrap();
+        let answers = rows(answer).len();
+        // every atom is a component on its own, with its head variables.
+        let head = query.head_atom.variables();
+        let component_answers: Vec<usize> = query
+            .body_atoms
+            .iter()
+            .map(|atom| {
+                let terms = atom
+                    .terms
+                    .iter()
+                    .filter(|term| matches!(term, Term::Variable(v) if head.contains(v)))
+                    .copied()
+                    .collect();
+                let component = ConjunctiveQuery {
+                    head_atom: Atom {
+                        name: "Answer",
+                        terms,
+                    },
+                    body_atoms: vec![atom.clone()],
+                };
+                rows(yannakaki(&component, &data, Options::default()).unwrap()).len()
+            })
+            .collect();
+        // the 5 beers of brewery 478 with each of the 11 categories.
+        assert_eq!(component_answers, vec![5, 11]);
+        assert_eq!(answers, component_answers.iter().product::<usize>());
+        assert!(explain.semijoins.is_empty());
+        assert!(explain.joins.iter().any(|join| join.cross_product));
+    }
+
+    #[test]
+    fn single_atom_query_is_a_selection_and_a_projection() {
+        let query = create_cq7();
+        let data = load(&query);
+        let (answer, explain) = yannakaki_explain(&query, &data, Options::default()).unwrap();
+        // the 9 breweries in Bamberg.
+        assert_eq!(rows(answer).len(), 9);
+        assert_eq!(explain.program.roots, vec!["Breweries".to_string()]);
+        assert_eq!(explain.selections.len(), 1);
+        assert_eq!(
+            explain.selections[0].input_rows,
+            data["Breweries"].num_rows()
+        );
+        assert_eq!(explain.selections[0].output_rows, 9);
+        assert!(explain.semijoins.is_empty());
+        assert!(explain.joins.is_empty());
+    }
 }